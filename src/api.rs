@@ -0,0 +1,311 @@
+use axum::{
+    extract::{ConnectInfo, Json, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Router,
+};
+use serde::{Serialize, Deserialize};
+use std::net::{IpAddr, SocketAddr};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+use crate::chat;
+use crate::choke::SlotStatus;
+use crate::download_state::DownloadManifest;
+use crate::manifest::FileManifest;
+use crate::events;
+use crate::lan::LanPeer;
+use crate::peer::{self, SharedState};
+use crate::piece_store;
+use crate::throttle::{self, ThrottleStatus};
+
+/// Requisição para compartilhar um arquivo local
+#[derive(Debug, Deserialize)]
+struct ShareRequest {
+    path: String, // Caminho do arquivo em qualquer diretório
+}
+
+/// Requisição para iniciar um download, pelo nome/ID ou por um manifesto importado
+#[derive(Debug, Deserialize)]
+struct DownloadRequest {
+    file: Option<String>,             // Nome ou ID do arquivo na rede
+    manifest: Option<FileManifest>,   // Manifesto exportado por outro peer
+    connections: Option<usize>,       // Conexões paralelas (padrão: limite do peer)
+}
+
+/// Requisição para enviar uma mensagem de chat
+#[derive(Debug, Deserialize)]
+struct ChatRequest {
+    to: String,      // Nome do peer ou endereço host:porta
+    message: String, // Texto da mensagem
+}
+
+/// Novos limites de banda; os campos ausentes mantêm o valor atual
+///
+/// Cada taxa aceita bytes por segundo com os sufixos `K` e `M` (ex: `"512K"`), e `"0"` remove o limite.
+#[derive(Debug, Deserialize)]
+struct LimitsRequest {
+    upload: Option<String>,
+    download: Option<String>,
+    peer_upload: Option<String>,
+    peer_download: Option<String>,
+}
+
+/// Arquivos presentes no diretório do peer e chunks que ele pode servir
+#[derive(Debug, Serialize)]
+struct LocalFiles {
+    files: Vec<String>,
+    chunks: Vec<String>,
+}
+
+/// Estado atual do peer
+#[derive(Debug, Serialize)]
+struct PeerStatus {
+    name: String,
+    address: String,
+    tracker_url: String,        // Tracker consultado primeiro no momento
+    trackers: Vec<Vec<String>>, // Todos os trackers, em níveis
+    data_dir: String,
+    local_chunks: usize,
+    max_connections: usize,
+    scheduler: String,
+    active_downloads: Vec<String>,
+    bandwidth: ThrottleStatus,
+    upload_slots: SlotStatus,
+}
+
+/// **Rotas de controle do peer (`/api/...`), usadas pelo frontend**
+///
+/// Só aceitam conexões vindas da própria máquina, já que permitem compartilhar
+/// caminhos arbitrários e iniciar downloads em nome do peer. Como qualquer página aberta
+/// no navegador também conecta de localhost, o CORS só libera as origens de `--api-origins`.
+pub fn routes(state: &SharedState) -> Router<SharedState> {
+    let origins: Vec<HeaderValue> = state.api_origins.iter()
+        .filter_map(|origin| origin.parse().ok())
+        .collect();
+
+    Router::new()
+        .route("/api/share", post(share))
+        .route("/api/download", post(download))
+        .route("/api/resume", post(resume))
+        .route("/api/files", get(local_files))
+        .route("/api/chat", post(send_chat))
+        .route("/api/status", get(status))
+        .route("/api/limits", get(limits).post(set_limits))
+        .route("/api/lan", get(lan_peers))
+        .route("/api/events", get(events::stream_events))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_local))
+        .layer(
+            CorsLayer::new()
+                .allow_origin(AllowOrigin::list(origins))
+                .allow_methods(Any)
+                .allow_headers(Any),
+        )
+}
+
+/// **Rejeita requisições de controle que não venham do próprio usuário**
+///
+/// Além da conexão de localhost, exige um `Host` de loopback (um site que aponte o próprio
+/// domínio para 127.0.0.1 chega com o seu nome em `Host`) e, se houver `Origin`, uma das
+/// origens configuradas: o navegador a envia em toda requisição de outra página.
+async fn require_local(
+    State(state): State<SharedState>,
+    ConnectInfo(remote): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let forbidden = |reason: &str| {
+        println!("🚫 Requisição de controle recusada de {}: {}", remote, reason);
+        (StatusCode::FORBIDDEN, Json("API de controle disponível apenas localmente".to_string())).into_response()
+    };
+
+    if !remote.ip().is_loopback() {
+        return forbidden("conexão externa");
+    }
+    let host = request.headers().get(header::HOST).and_then(|host| host.to_str().ok()).unwrap_or_default();
+    if !is_loopback_host(host) {
+        return forbidden(&format!("Host '{}'", host));
+    }
+    if let Some(origin) = request.headers().get(header::ORIGIN) {
+        if !state.api_origins.iter().any(|allowed| origin == allowed.as_str()) {
+            return forbidden(&format!("origem {:?}", origin));
+        }
+    }
+    next.run(request).await
+}
+
+/// Indica se o `Host` da requisição é `localhost` ou um IP de loopback, com ou sem porta
+fn is_loopback_host(host: &str) -> bool {
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if !name.ends_with(':') && port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    };
+    let name = name.trim_start_matches('[').trim_end_matches(']');
+    name.eq_ignore_ascii_case("localhost") || name.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Indica se `path` fica dentro de um dos diretórios de `--share-dirs` (qualquer caminho se não houver nenhum)
+fn is_shareable(state: &SharedState, path: &str) -> bool {
+    if state.share_dirs.is_empty() {
+        return true;
+    }
+    let Ok(path) = std::fs::canonicalize(path) else {
+        return false;
+    };
+    state.share_dirs.iter()
+        .filter_map(|dir| std::fs::canonicalize(dir).ok())
+        .any(|dir| path.starts_with(dir))
+}
+
+/// **Compartilha um arquivo local na rede**
+async fn share(
+    State(state): State<SharedState>,
+    Json(payload): Json<ShareRequest>,
+) -> (StatusCode, Json<String>) {
+    if !is_shareable(&state, &payload.path) {
+        return (StatusCode::FORBIDDEN, Json(format!("'{}' está fora dos diretórios compartilháveis", payload.path)));
+    }
+    match peer::register_chunks(&state, &payload.path).await {
+        Ok(()) => (StatusCode::OK, Json(format!("Arquivo '{}' processado", payload.path))),
+        Err(e) => (e.status(), Json(format!("Erro ao compartilhar '{}': {}", payload.path, e))),
+    }
+}
+
+/// **Inicia um download em segundo plano**
+async fn download(
+    State(state): State<SharedState>,
+    Json(payload): Json<DownloadRequest>,
+) -> (StatusCode, Json<String>) {
+    let manifest = match (payload.manifest, payload.file) {
+        (Some(manifest), _) => match manifest.verify() {
+            Ok(()) => manifest,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(format!("Manifesto inválido: {}", e))),
+        },
+        (None, Some(file)) if !file.trim().is_empty() => match peer::resolve_file(&state, &file).await {
+            Ok(manifest) => manifest,
+            Err(e) => return (e.status(), Json(format!("Erro ao localizar '{}': {}", file, e))),
+        },
+        _ => return (StatusCode::BAD_REQUEST, Json("Informe o nome do arquivo ou um manifesto".to_string())),
+    };
+
+    if !peer::reserve_download(&state, &manifest.id) {
+        return (StatusCode::CONFLICT, Json(format!("Download de '{}' já está em andamento", manifest.file_name)));
+    }
+
+    let connections = peer::choose_connections(&state, payload.connections);
+    let message = format!("Download de '{}' ({}) iniciado com {} conexões", manifest.file_name, manifest.id, connections);
+
+    tokio::spawn(async move {
+        peer::download_reserved(&state, manifest, connections).await;
+    });
+
+    (StatusCode::ACCEPTED, Json(message))
+}
+
+/// **Retoma em segundo plano os downloads interrompidos**
+async fn resume(State(state): State<SharedState>) -> (StatusCode, Json<String>) {
+    let pending = DownloadManifest::pending(&state.storage);
+    if pending.is_empty() {
+        return (StatusCode::OK, Json("Nenhum download pendente".to_string()));
+    }
+
+    tokio::spawn(async move {
+        peer::resume_downloads(&state).await;
+    });
+
+    (StatusCode::ACCEPTED, Json(format!("Retomando {} download(s)", pending.len())))
+}
+
+/// **Lista os arquivos e chunks locais**
+async fn local_files(State(state): State<SharedState>) -> Json<LocalFiles> {
+    let mut chunks: Vec<String> = piece_store::available_chunks(&state.storage).into_iter().collect();
+    chunks.sort();
+
+    Json(LocalFiles {
+        files: piece_store::complete_files(&state.storage).into_iter().map(|file| file.file_name).collect(),
+        chunks,
+    })
+}
+
+/// **Envia uma mensagem de chat para outro peer**
+async fn send_chat(
+    State(state): State<SharedState>,
+    Json(payload): Json<ChatRequest>,
+) -> (StatusCode, Json<String>) {
+    let recipient_address = match peer::resolve_peer_address(&state, &payload.to).await {
+        Ok(address) => address,
+        Err(e) => return (e.status(), Json(format!("Erro ao localizar '{}': {}", payload.to, e))),
+    };
+
+    let chat_message = chat::ChatMessage {
+        sender: state.name.clone(),
+        message: payload.message,
+        timestamp: chat::current_timestamp(),
+    };
+
+    // Uma recusa do destinatário volta com o mesmo código; falhas de conexão viram `502`
+    match chat::send_chat_message(&recipient_address, chat_message).await {
+        Ok(()) => (StatusCode::OK, Json(format!("Mensagem enviada para {}", recipient_address))),
+        Err(e) => {
+            let status = e.status()
+                .and_then(|status| StatusCode::from_u16(status.as_u16()).ok())
+                .unwrap_or(StatusCode::BAD_GATEWAY);
+            (status, Json(format!("Erro ao enviar a mensagem: {}", e)))
+        }
+    }
+}
+
+/// **Retorna o estado atual do peer**
+async fn status(State(state): State<SharedState>) -> Json<PeerStatus> {
+    let mut active_downloads: Vec<String> = state.downloads.lock().unwrap().iter().cloned().collect();
+    active_downloads.sort();
+
+    Json(PeerStatus {
+        name: state.name.clone(),
+        address: state.address.clone(),
+        tracker_url: state.trackers.primary(),
+        trackers: state.trackers.tiers(),
+        data_dir: state.storage.root().display().to_string(),
+        local_chunks: peer::count_local_chunks(&state.storage),
+        max_connections: peer::determine_max_connections(&state),
+        scheduler: state.scheduler.clone(),
+        active_downloads,
+        bandwidth: state.throttle.status(),
+        upload_slots: state.upload_slots.status(),
+    })
+}
+
+/// **Retorna os peers encontrados na rede local** (vazio sem `--lan`)
+async fn lan_peers(State(state): State<SharedState>) -> Json<Vec<LanPeer>> {
+    Json(state.lan.as_ref().map(|lan| lan.peers()).unwrap_or_default())
+}
+
+/// **Retorna os limites de banda e as taxas atuais de envio e recebimento**
+async fn limits(State(state): State<SharedState>) -> Json<ThrottleStatus> {
+    Json(state.throttle.status())
+}
+
+/// **Altera os limites de banda sem reiniciar o peer**
+async fn set_limits(
+    State(state): State<SharedState>,
+    Json(payload): Json<LimitsRequest>,
+) -> (StatusCode, Json<String>) {
+    let mut limits = state.throttle.limits();
+    let changes = [
+        ("upload", payload.upload),
+        ("download", payload.download),
+        ("peer_upload", payload.peer_upload),
+        ("peer_download", payload.peer_download),
+    ];
+    for (name, value) in changes {
+        let Some(value) = value else { continue };
+        if let Err(e) = throttle::parse_rate(&value).and_then(|rate| limits.set(name, rate)) {
+            return (StatusCode::BAD_REQUEST, Json(e));
+        }
+    }
+
+    state.throttle.set_limits(limits);
+    println!("🚦 Limites de banda atualizados: {:?}", limits);
+    (StatusCode::OK, Json("Limites de banda atualizados".to_string()))
+}
@@ -1,5 +1,5 @@
 ﻿use axum::{
-    extract::Json,
+    extract::{Json, State},
     response::IntoResponse,
     http::StatusCode,
 };
@@ -7,6 +7,9 @@ use serde::{Serialize, Deserialize};
 use reqwest::Client;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::events::PeerEvent;
+use crate::peer::SharedState;
+
 /// Estrutura que representa uma mensagem de chat entre peers.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChatMessage {
@@ -25,9 +28,18 @@ pub fn current_timestamp() -> u64 {
 
 /// Handler para receber mensagens de chat via endpoint `/chat`.
 ///
-/// Ao receber uma mensagem, o handler a exibe no console e responde com um OK.
-pub async fn receive_chat(Json(payload): Json<ChatMessage>) -> impl IntoResponse {
+/// Ao receber uma mensagem, o handler a exibe no console, publica um evento
+/// `ChatReceived` para a interface e responde com um OK.
+pub async fn receive_chat(
+    State(state): State<SharedState>,
+    Json(payload): Json<ChatMessage>,
+) -> impl IntoResponse {
     println!("[CHAT] {} diz: {}", payload.sender, payload.message);
+    state.events.publish(PeerEvent::ChatReceived {
+        sender: payload.sender,
+        message: payload.message,
+        timestamp: payload.timestamp,
+    });
     (StatusCode::OK, Json("Mensagem recebida".to_string()))
 }
 
@@ -37,7 +49,8 @@ pub async fn receive_chat(Json(payload): Json<ChatMessage>) -> impl IntoResponse
 /// - `recipient_address`: endereço do peer (ex: "127.0.0.1:8000") que receberá a mensagem.
 /// - `chat_message`: estrutura com os dados da mensagem.
 ///
-/// Retorna um `Result` indicando se a mensagem foi enviada com sucesso.
+/// Retorna um `Result` indicando se a mensagem foi enviada com sucesso; uma resposta
+/// diferente de 2xx do destinatário também é um erro, com o status em `reqwest::Error::status`.
 pub async fn send_chat_message(
     recipient_address: &str,
     chat_message: ChatMessage,
@@ -45,16 +58,12 @@ pub async fn send_chat_message(
     let client = Client::new();
     let url = format!("http://{}/chat", recipient_address);
     
-    let response = client.post(&url)
+    client.post(&url)
         .json(&chat_message)
         .send()
-        .await?;
-    
-    if response.status().is_success() {
-        println!("✅ Mensagem enviada para {}!", recipient_address);
-    } else {
-        println!("❌ Falha ao enviar mensagem para {}: HTTP {}", recipient_address, response.status());
-    }
+        .await?
+        .error_for_status()?;
     
+    println!("✅ Mensagem enviada para {}!", recipient_address);
     Ok(())
 }
\ No newline at end of file
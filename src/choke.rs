@@ -0,0 +1,275 @@
+use rand::prelude::IteratorRandom;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+/// Estratégias aceitas por `--unchoke`
+pub const UNCHOKE_STRATEGIES: &[&str] = &["round-robin", "tit-for-tat"];
+
+/// Vagas de upload padrão
+pub const DEFAULT_UPLOAD_SLOTS: usize = 4;
+
+/// Cabeçalho com o endereço anunciado de quem pede um chunk, para identificar o peer
+pub const PEER_HEADER: &str = "x-peer-address";
+
+/// Cabeçalho que marca a resposta de um peer sem vagas (`choke`)
+pub const CHOKED_HEADER: &str = "x-choked";
+
+/// Intervalo entre as redistribuições das vagas
+pub const ROUND_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A vaga otimista troca de dono a cada tantas rodadas
+const OPTIMISTIC_ROUNDS: u64 = 3;
+
+/// Peers sem pedidos há mais que isso deixam de ser considerados interessados
+const IDLE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// **Como as vagas regulares são distribuídas a cada rodada**
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnchokeStrategy {
+    /// Revezamento: quem espera há mais tempo ganha a vaga
+    #[default]
+    RoundRobin,
+    /// Reciprocidade: as vagas vão para quem mais nos enviou dados na última rodada
+    TitForTat,
+}
+
+impl UnchokeStrategy {
+    pub fn from_name(name: &str) -> Option<UnchokeStrategy> {
+        match name {
+            "round-robin" => Some(UnchokeStrategy::RoundRobin),
+            "tit-for-tat" => Some(UnchokeStrategy::TitForTat),
+            _ => None,
+        }
+    }
+}
+
+/// Um peer que pediu chunks a este peer
+struct Requester {
+    last_request: Instant,          // Último pedido recebido
+    waiting_since: Option<Instant>, // Desde quando está sem vaga (`None` se tem vaga)
+}
+
+struct SlotState {
+    requesters: HashMap<String, Requester>,
+    unchoked: HashSet<String>,     // Peers com vaga, inclusive o otimista
+    optimistic: Option<String>,    // Dono da vaga otimista
+    received: HashMap<String, u64>, // Bytes recebidos de cada peer na rodada atual
+    round: u64,
+    next_round: Instant,
+}
+
+/// **Vagas de upload ocupadas e em espera**, como mostradas pela API
+#[derive(Debug, Clone, Serialize)]
+pub struct SlotStatus {
+    pub slots: usize,
+    pub strategy: UnchokeStrategy,
+    pub unchoked: Vec<String>,
+    pub optimistic: Option<String>,
+    pub choked: Vec<String>,
+}
+
+/// **Controle das vagas de upload (choke/unchoke)**
+///
+/// Só os peers com vaga recebem chunks; os demais recebem uma resposta `choke` e tentam
+/// de novo depois (ou baixam de outro peer). Enquanto houver vaga livre, quem pede entra
+/// na hora. A cada `ROUND_INTERVAL` as vagas regulares são redistribuídas conforme a
+/// estratégia, e uma vaga é reservada a um peer sorteado entre os que esperam (unchoke
+/// otimista), trocado a cada `OPTIMISTIC_ROUNDS` rodadas, para que peers novos, que ainda
+/// não têm o que oferecer, também consigam começar.
+pub struct UploadSlots {
+    slots: usize,
+    strategy: UnchokeStrategy,
+    state: Mutex<SlotState>,
+}
+
+impl UploadSlots {
+    pub fn new(slots: usize, strategy: UnchokeStrategy) -> Self {
+        UploadSlots {
+            slots: slots.max(1),
+            strategy,
+            state: Mutex::new(SlotState {
+                requesters: HashMap::new(),
+                unchoked: HashSet::new(),
+                optimistic: None,
+                received: HashMap::new(),
+                round: 0,
+                next_round: Instant::now() + ROUND_INTERVAL,
+            }),
+        }
+    }
+
+    /// **Pedido de um chunk pelo peer `peer`**
+    ///
+    /// Retorna `Err` com o tempo até a próxima rodada se o peer estiver sem vaga.
+    pub fn request(&self, peer: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let has_slot = state.unchoked.contains(peer);
+        let free_slot = state.unchoked.len() < self.slots;
+
+        let requester = state.requesters.entry(peer.to_string()).or_insert(Requester { last_request: now, waiting_since: Some(now) });
+        requester.last_request = now;
+        if has_slot {
+            return Ok(());
+        }
+        if free_slot {
+            requester.waiting_since = None;
+            state.unchoked.insert(peer.to_string());
+            println!("🎟️ Vaga de upload liberada para '{}'", peer);
+            return Ok(());
+        }
+
+        Err(state.next_round.saturating_duration_since(now).max(Duration::from_secs(1)))
+    }
+
+    /// Registra bytes recebidos de um peer, usados pela estratégia `tit-for-tat`
+    pub fn record_received(&self, peer: &str, bytes: usize) {
+        *self.state.lock().unwrap().received.entry(peer.to_string()).or_default() += bytes as u64;
+    }
+
+    /// **Redistribui as vagas** (chamado a cada `ROUND_INTERVAL`)
+    pub fn rechoke(&self) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.round += 1;
+        state.next_round = now + ROUND_INTERVAL;
+
+        // 🧹 Peers que pararam de pedir liberam a vaga
+        state.requesters.retain(|_, requester| now.duration_since(requester.last_request) <= IDLE_TIMEOUT);
+        let SlotState { requesters, unchoked, optimistic, received, round, .. } = &mut *state;
+        unchoked.retain(|peer| requesters.contains_key(peer));
+        if optimistic.as_ref().is_some_and(|peer| !requesters.contains_key(peer)) {
+            *optimistic = None;
+        }
+
+        // 🏅 Vagas regulares: todas menos a otimista
+        let mut ranked: Vec<&String> = requesters.keys().collect();
+        match self.strategy {
+            UnchokeStrategy::RoundRobin => ranked.sort_by_key(|peer| waiting_rank(&requesters[*peer])),
+            UnchokeStrategy::TitForTat => ranked.sort_by_key(|peer| {
+                (std::cmp::Reverse(received.get(*peer).copied().unwrap_or(0)), waiting_rank(&requesters[*peer]))
+            }),
+        }
+        let regular: HashSet<String> = ranked.into_iter().take(self.slots - 1).cloned().collect();
+
+        // 🎲 Vaga otimista: mantida por algumas rodadas, depois sorteada entre os que sobraram
+        let keep_optimistic = *round % OPTIMISTIC_ROUNDS != 0
+            && optimistic.as_ref().is_some_and(|peer| !regular.contains(peer));
+        if !keep_optimistic {
+            *optimistic = requesters.keys()
+                .filter(|peer| !regular.contains(*peer))
+                .choose(&mut rand::thread_rng())
+                .cloned();
+        }
+
+        let mut next: HashSet<String> = regular;
+        next.extend(optimistic.iter().cloned());
+        for (peer, requester) in requesters.iter_mut() {
+            match (next.contains(peer), requester.waiting_since) {
+                (true, Some(_)) => {
+                    requester.waiting_since = None;
+                    println!("🎟️ Vaga de upload liberada para '{}'", peer);
+                }
+                (false, None) => {
+                    requester.waiting_since = Some(now);
+                    println!("🚧 '{}' perdeu a vaga de upload (choke)", peer);
+                }
+                _ => {}
+            }
+        }
+        *unchoked = next;
+
+        // A reciprocidade considera só a rodada mais recente
+        received.clear();
+    }
+
+    pub fn status(&self) -> SlotStatus {
+        let state = self.state.lock().unwrap();
+        let mut unchoked: Vec<String> = state.unchoked.iter().cloned().collect();
+        unchoked.sort();
+        let mut choked: Vec<String> = state.requesters.keys().filter(|peer| !state.unchoked.contains(*peer)).cloned().collect();
+        choked.sort();
+
+        SlotStatus {
+            slots: self.slots,
+            strategy: self.strategy,
+            unchoked,
+            optimistic: state.optimistic.clone(),
+            choked,
+        }
+    }
+}
+
+/// Ordem de espera: primeiro quem está sem vaga há mais tempo, depois quem já tem vaga
+fn waiting_rank(requester: &Requester) -> (bool, Option<Instant>) {
+    (requester.waiting_since.is_none(), requester.waiting_since)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Vagas com `peers` pedindo chunks, na ordem dada
+    fn slots_with(slots: usize, strategy: UnchokeStrategy, peers: &[&str]) -> UploadSlots {
+        let upload_slots = UploadSlots::new(slots, strategy);
+        for peer in peers {
+            let _ = upload_slots.request(peer);
+        }
+        upload_slots
+    }
+
+    #[test]
+    fn free_slots_are_taken_on_request() {
+        let upload_slots = slots_with(2, UnchokeStrategy::RoundRobin, &["a", "b"]);
+        assert_eq!(upload_slots.request("a"), Ok(()));
+
+        let wait = upload_slots.request("c").unwrap_err();
+        assert!(wait >= Duration::from_secs(1) && wait <= ROUND_INTERVAL);
+        let status = upload_slots.status();
+        assert_eq!(status.unchoked, ["a", "b"]);
+        assert_eq!(status.choked, ["c"]);
+    }
+
+    #[test]
+    fn round_robin_gives_the_slot_to_the_longest_waiting() {
+        let upload_slots = slots_with(2, UnchokeStrategy::RoundRobin, &["a", "b", "c"]);
+        upload_slots.rechoke();
+
+        let status = upload_slots.status();
+        assert_eq!(status.unchoked.len(), 2);
+        assert!(status.unchoked.contains(&"c".to_string()));
+        assert_eq!(upload_slots.request("c"), Ok(()));
+    }
+
+    #[test]
+    fn tit_for_tat_keeps_the_best_uploader() {
+        let upload_slots = slots_with(2, UnchokeStrategy::TitForTat, &["a", "b", "c"]);
+        upload_slots.record_received("b", 1000);
+        upload_slots.record_received("a", 10);
+        upload_slots.rechoke();
+
+        let status = upload_slots.status();
+        assert_eq!(status.unchoked.len(), 2);
+        assert!(status.unchoked.contains(&"b".to_string()));
+        assert_ne!(status.optimistic.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn optimistic_slot_is_kept_until_the_rotation() {
+        let upload_slots = slots_with(1, UnchokeStrategy::RoundRobin, &["a", "b", "c"]);
+        upload_slots.rechoke();
+        let first = upload_slots.status().optimistic;
+        assert!(first.is_some());
+
+        // Só a vaga otimista existe, e ela fica com o mesmo peer até a rodada `OPTIMISTIC_ROUNDS`
+        for _ in 1..OPTIMISTIC_ROUNDS - 1 {
+            upload_slots.rechoke();
+            let status = upload_slots.status();
+            assert_eq!(status.optimistic, first);
+            assert_eq!(status.unchoked, first.iter().cloned().collect::<Vec<_>>());
+        }
+    }
+}
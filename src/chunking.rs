@@ -0,0 +1,217 @@
+use serde::{Serialize, Deserialize};
+
+/// Modos de divisão aceitos por `--chunking`
+pub const CHUNKING_MODES: &[&str] = &["fixed", "cdc"];
+
+/// Menor e maior tamanho de chunk aceitos em `--chunk-size`
+pub const MIN_CHUNK_SIZE: usize = 4 * 1024;
+pub const MAX_CHUNK_SIZE: usize = 64 * 1024 * 1024;
+
+/// Limites do tamanho escolhido automaticamente
+const AUTO_MIN_CHUNK_SIZE: usize = 64 * 1024;
+const AUTO_MAX_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Quantidade aproximada de chunks buscada pelo tamanho automático
+const AUTO_TARGET_CHUNKS: u64 = 256;
+
+/// **Como os chunks de um arquivo foram delimitados**
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChunkingMode {
+    /// Chunks de tamanho fixo (o último pode ser menor)
+    #[default]
+    Fixed,
+    /// Chunks delimitados pelo conteúdo (rolling hash), com tamanho médio `chunk_size`
+    Cdc,
+}
+
+impl ChunkingMode {
+    /// Converte o nome usado na configuração (`fixed` ou `cdc`)
+    pub fn from_name(name: &str) -> Option<ChunkingMode> {
+        match name {
+            "fixed" => Some(ChunkingMode::Fixed),
+            "cdc" => Some(ChunkingMode::Cdc),
+            _ => None,
+        }
+    }
+}
+
+/// **Opções de divisão usadas ao compartilhar um arquivo**
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChunkingOptions {
+    pub mode: ChunkingMode,
+    pub chunk_size: Option<usize>, // Tamanho (ou tamanho médio, em `cdc`); `None` escolhe pelo tamanho do arquivo
+}
+
+impl ChunkingOptions {
+    /// Tamanho de chunk para um arquivo de `file_size` bytes
+    pub fn chunk_size_for(&self, file_size: u64) -> usize {
+        self.chunk_size.unwrap_or_else(|| auto_chunk_size(file_size))
+    }
+}
+
+/// **Tamanho de chunk automático**: a potência de 2 que deixa o arquivo com cerca de
+/// `AUTO_TARGET_CHUNKS` chunks, entre 64 KiB e 16 MiB
+///
+/// Arquivos pequenos ganham chunks menores (e podem ser baixados de vários peers ao mesmo
+/// tempo); arquivos grandes, chunks maiores (menos arquivos em disco e registros no tracker).
+pub fn auto_chunk_size(file_size: u64) -> usize {
+    let target = (file_size / AUTO_TARGET_CHUNKS).max(1).next_power_of_two();
+    (target as usize).clamp(AUTO_MIN_CHUNK_SIZE, AUTO_MAX_CHUNK_SIZE)
+}
+
+/// Lê um tamanho de chunk em bytes, aceitando os sufixos `K` e `M` (ex: `512K`, `4M`)
+pub fn parse_chunk_size(value: &str) -> Result<usize, String> {
+    let upper = value.trim().to_uppercase();
+    let (digits, multiplier) = match upper.trim_end_matches("IB").trim_end_matches('B') {
+        number if number.ends_with('K') => (number.trim_end_matches('K').to_string(), 1024),
+        number if number.ends_with('M') => (number.trim_end_matches('M').to_string(), 1024 * 1024),
+        number => (number.to_string(), 1),
+    };
+
+    match digits.trim().parse::<usize>().ok().and_then(|size| size.checked_mul(multiplier)) {
+        Some(size) if (MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&size) => Ok(size),
+        _ => Err(format!("Tamanho de chunk inválido: '{}' (use auto ou entre 4K e 64M)", value)),
+    }
+}
+
+/// Tabela do gear hash: 256 valores pseudoaleatórios fixos (splitmix64), iguais em todos os peers
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// **Decide onde cada chunk termina enquanto o arquivo é lido**
+///
+/// No modo `cdc` (estilo FastCDC), um gear hash percorre os bytes e o chunk termina quando
+/// os bits altos do hash zeram, respeitando um mínimo de `chunk_size / 4` e um máximo de
+/// `chunk_size * 4`. Como os cortes dependem só do conteúdo próximo, uma edição no meio de
+/// um arquivo grande muda apenas os chunks vizinhos, e o restante continua com os mesmos hashes.
+pub struct Chunker {
+    mode: ChunkingMode,
+    chunk_size: usize,
+    min_size: usize,
+    max_size: usize,
+    shift: u32,       // Bits descartados do hash; o corte acontece quando os bits restantes zeram
+    fingerprint: u64, // Hash dos bytes do chunk atual
+}
+
+impl Chunker {
+    pub fn new(mode: ChunkingMode, chunk_size: usize) -> Self {
+        let chunk_size = chunk_size.max(1);
+        Chunker {
+            mode,
+            chunk_size,
+            min_size: (chunk_size / 4).max(1),
+            max_size: chunk_size.saturating_mul(4),
+            shift: 64 - chunk_size.ilog2().max(1),
+            fingerprint: 0,
+        }
+    }
+
+    /// Procura o fim do chunk atual em `data`, sabendo que o chunk já tem `chunk_len` bytes
+    ///
+    /// Retorna quantos bytes de `data` completam o chunk, ou `None` se ele continua no próximo bloco.
+    pub fn find_cut(&mut self, data: &[u8], chunk_len: usize) -> Option<usize> {
+        match self.mode {
+            ChunkingMode::Fixed => {
+                let remaining = self.chunk_size - chunk_len;
+                (remaining <= data.len()).then_some(remaining)
+            }
+            ChunkingMode::Cdc => {
+                for (i, byte) in data.iter().enumerate() {
+                    self.fingerprint = (self.fingerprint << 1).wrapping_add(GEAR[*byte as usize]);
+                    let len = chunk_len + i + 1;
+                    if len >= self.max_size || (len >= self.min_size && self.fingerprint >> self.shift == 0) {
+                        self.fingerprint = 0;
+                        return Some(i + 1);
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes pseudoaleatórios fixos (xorshift), para que os cortes sejam sempre os mesmos
+    fn sample(len: usize) -> Vec<u8> {
+        let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    /// Divide `data` lendo blocos de `block` bytes, como `split_file`, e retorna os chunks
+    fn split(data: &[u8], mode: ChunkingMode, chunk_size: usize, block: usize) -> Vec<&[u8]> {
+        let mut chunker = Chunker::new(mode, chunk_size);
+        let mut chunks = vec![];
+        let mut start = 0;
+        for (number, data_block) in data.chunks(block).enumerate() {
+            let offset = number * block;
+            let mut position = 0;
+            while let Some(cut) = chunker.find_cut(&data_block[position..], offset + position - start) {
+                position += cut;
+                chunks.push(&data[start..offset + position]);
+                start = offset + position;
+            }
+        }
+        if start < data.len() {
+            chunks.push(&data[start..]);
+        }
+        chunks
+    }
+
+    #[test]
+    fn cdc_cuts_do_not_depend_on_the_read_blocks() {
+        let data = sample(256 * 1024);
+        let whole = split(&data, ChunkingMode::Cdc, 4096, data.len());
+        assert_eq!(split(&data, ChunkingMode::Cdc, 4096, 1000), whole);
+        assert_eq!(whole.concat(), data);
+
+        let (_, last) = whole.split_last().unwrap();
+        assert!(!last.is_empty());
+        for chunk in &whole[..whole.len() - 1] {
+            assert!((1024..=16 * 1024).contains(&chunk.len()), "chunk de {} bytes", chunk.len());
+        }
+    }
+
+    #[test]
+    fn cdc_cuts_resynchronize_after_an_insertion() {
+        let data = sample(256 * 1024);
+        let middle = data.len() / 2;
+        let edited: Vec<u8> = [&data[..middle], b"texto inserido", &data[middle..]].concat();
+
+        let before = split(&data, ChunkingMode::Cdc, 4096, 1000);
+        let after = split(&edited, ChunkingMode::Cdc, 4096, 1000);
+        let changed = after.iter().filter(|chunk| !before.contains(chunk)).count();
+        assert!(before.len() > 30);
+        assert!(changed <= 2, "{} de {} chunks mudaram", changed, after.len());
+
+        // Com tamanho fixo, todos os chunks depois da inserção mudam
+        let fixed_before = split(&data, ChunkingMode::Fixed, 4096, 1000);
+        let fixed_after = split(&edited, ChunkingMode::Fixed, 4096, 1000);
+        let fixed_changed = fixed_after.iter().filter(|chunk| !fixed_before.contains(chunk)).count();
+        assert!(fixed_changed > fixed_after.len() / 3);
+    }
+}
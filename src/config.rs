@@ -0,0 +1,485 @@
+use serde::{de::DeserializeOwned, Serialize, Deserialize}; // Leitura do arquivo de configuração em JSON
+use std::collections::HashMap;
+use std::net::SocketAddrV4;
+use std::{env, fs};
+
+use crate::choke::{DEFAULT_UPLOAD_SLOTS, UNCHOKE_STRATEGIES};
+use crate::chunking::{self, CHUNKING_MODES};
+use crate::dht::DHT_MODES;
+use crate::lan::DEFAULT_LAN_GROUP;
+use crate::scheduler::SCHEDULERS;
+use crate::throttle::{self, RateLimits};
+
+/// URL padrão do tracker quando nada for configurado
+pub const DEFAULT_TRACKER_URL: &str = "http://127.0.0.1:9500";
+
+/// Porta padrão do tracker
+pub const DEFAULT_TRACKER_PORT: u16 = 9500;
+
+/// Limite padrão de conexões paralelas por download
+pub const DEFAULT_MAX_CONNECTIONS: usize = 4;
+
+/// Políticas aceitas por `--connection-policy`: sempre `max_connections` ou crescer com os chunks locais
+pub const CONNECTION_POLICIES: &[&str] = &["fixed", "chunks"];
+
+/// Origens aceitas pela API de controle por padrão (servidor de desenvolvimento do frontend)
+pub const DEFAULT_API_ORIGINS: &[&str] = &["http://localhost:5173", "http://127.0.0.1:5173"];
+
+/// Flags booleanas, que não consomem o argumento seguinte (ex: `--seed`, `--lan`)
+const SWITCHES: &[&str] = &["seed", "lan"];
+
+/// **Argumentos de linha de comando separados em flags e posicionais**
+///
+/// Flags seguem o formato `--chave valor` ou `--chave=valor`; as de `SWITCHES` não recebem valor.
+#[derive(Debug, Default)]
+pub struct CliArgs {
+    pub positional: Vec<String>,
+    pub flags: HashMap<String, String>,
+}
+
+impl CliArgs {
+    /// Separa os argumentos recebidos em flags e posicionais
+    pub fn parse(args: &[String]) -> CliArgs {
+        let mut parsed = CliArgs::default();
+        let mut iter = args.iter();
+
+        while let Some(arg) = iter.next() {
+            match arg.strip_prefix("--") {
+                Some(flag) => match flag.split_once('=') {
+                    Some((key, value)) => {
+                        parsed.flags.insert(key.to_string(), value.to_string());
+                    }
+                    None if SWITCHES.contains(&flag) => {
+                        parsed.flags.insert(flag.to_string(), "true".to_string());
+                    }
+                    None => {
+                        let value = iter.next().cloned().unwrap_or_default();
+                        parsed.flags.insert(flag.to_string(), value);
+                    }
+                },
+                None => parsed.positional.push(arg.clone()),
+            }
+        }
+
+        parsed
+    }
+
+    /// Retorna o valor de uma flag, se informada
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.flags.get(key).map(|value| value.as_str())
+    }
+
+    /// Indica se uma flag booleana foi ativada
+    pub fn has(&self, key: &str) -> bool {
+        matches!(self.get(key), Some("true" | "1" | "yes"))
+    }
+}
+
+/// **Configuração carregada em camadas**
+///
+/// Ordem de prioridade: valores padrão < arquivo de configuração < variáveis de ambiente < flags.
+/// Cada opção `nome_da_opcao` aceita a flag `--nome-da-opcao`, a variável
+/// `{ENV_PREFIX}NOME_DA_OPCAO` e a chave `nome_da_opcao` no arquivo JSON.
+pub trait LayeredConfig: Default + DeserializeOwned {
+    /// Opções reconhecidas, usadas para ler o ambiente e as flags
+    const OPTIONS: &'static [&'static str];
+    /// Prefixo das variáveis de ambiente (ex: `P2P_`)
+    const ENV_PREFIX: &'static str;
+    /// Arquivo de configuração lido automaticamente, se existir
+    const DEFAULT_FILE: &'static str;
+
+    /// Aplica uma opção individual vinda do ambiente ou da linha de comando
+    fn apply(&mut self, key: &str, value: &str) -> Result<(), String>;
+
+    /// Ajustes finais e validação depois de todas as camadas serem aplicadas
+    ///
+    /// O arquivo JSON é lido direto na struct, sem passar por `apply`, então os valores que
+    /// precisam de validação são conferidos aqui.
+    fn finish(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// **Carrega a configuração combinando arquivo, ambiente e linha de comando**
+    fn load(args: &CliArgs) -> Result<Self, String> {
+        let mut config = match config_file_path::<Self>(args) {
+            Some(path) => {
+                let content = fs::read_to_string(&path)
+                    .map_err(|e| format!("Erro ao ler '{}': {}", path, e))?;
+                serde_json::from_str(&content)
+                    .map_err(|e| format!("Arquivo de configuração '{}' inválido: {}", path, e))?
+            }
+            None => Self::default(),
+        };
+
+        for key in Self::OPTIONS {
+            if let Ok(value) = env::var(env_name::<Self>(key)) {
+                config.apply(key, &value)?;
+            }
+        }
+
+        for (flag, value) in &args.flags {
+            let key = flag.replace('-', "_");
+            if Self::OPTIONS.contains(&key.as_str()) {
+                config.apply(&key, value)?;
+            }
+        }
+
+        config.finish()?;
+        Ok(config)
+    }
+}
+
+/// **Configuração do peer**
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PeerConfig {
+    pub name: Option<String>,           // Nome do peer (perguntado no terminal se ausente)
+    pub tracker_url: String,            // URL do tracker (ex: http://10.0.0.5:9500)
+    pub trackers: Vec<Vec<String>>,     // Níveis de trackers (substituem `tracker_url` se houver)
+    pub bind_host: String,              // Interface onde o servidor do peer escuta
+    pub advertise_host: Option<String>, // Host anunciado ao tracker para os outros peers
+    pub port: Option<u16>,              // Porta do peer (aleatória entre 8000 e 9000 se ausente)
+    pub data_dir: Option<String>,       // Diretório de dados do peer (padrão: p2p_data/<nome>)
+    pub scheduler: String,              // Escolha de chunks nos downloads: "rarest" ou "random"
+    pub max_connections: usize,         // Limite de conexões paralelas por download
+    pub connection_policy: String,      // Conexões por padrão: "fixed" (o limite) ou "chunks" (crescem com os chunks locais)
+    pub chunk_size: Option<usize>,      // Tamanho dos chunks em bytes (automático se ausente)
+    pub chunking: String,               // Divisão dos arquivos: "fixed" ou "cdc" (pelo conteúdo)
+    pub upload_limit: u64,              // Limite total de envio em bytes/s (0 = sem limite)
+    pub download_limit: u64,            // Limite total de recebimento em bytes/s
+    pub peer_upload_limit: u64,         // Limite de envio para cada peer em bytes/s
+    pub peer_download_limit: u64,       // Limite de recebimento de cada peer em bytes/s
+    pub upload_slots: usize,            // Peers atendidos ao mesmo tempo nos uploads
+    pub unchoke: String,                // Distribuição das vagas: "round-robin" ou "tit-for-tat"
+    pub dht: String,                    // Uso da DHT: "off", "fallback" ou "only" (sem tracker)
+    pub dht_bootstrap: Vec<String>,     // Nós conhecidos para entrar na DHT (host:porta)
+    pub lan: bool,                      // Descoberta de peers na rede local por multicast
+    pub lan_group: SocketAddrV4,        // Grupo multicast e porta dos beacons
+    pub api_origins: Vec<String>,       // Origens (frontend) aceitas pela API de controle
+    pub share_dirs: Vec<String>,        // Diretórios que `/api/share` pode compartilhar (vazio = qualquer um)
+}
+
+impl Default for PeerConfig {
+    fn default() -> Self {
+        PeerConfig {
+            name: None,
+            tracker_url: DEFAULT_TRACKER_URL.to_string(),
+            trackers: vec![],
+            bind_host: "127.0.0.1".to_string(),
+            advertise_host: None,
+            port: None,
+            data_dir: None,
+            scheduler: "rarest".to_string(),
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            connection_policy: "fixed".to_string(),
+            chunk_size: None,
+            chunking: "fixed".to_string(),
+            upload_limit: 0,
+            download_limit: 0,
+            peer_upload_limit: 0,
+            peer_download_limit: 0,
+            upload_slots: DEFAULT_UPLOAD_SLOTS,
+            unchoke: "round-robin".to_string(),
+            dht: "off".to_string(),
+            dht_bootstrap: vec![],
+            lan: false,
+            lan_group: DEFAULT_LAN_GROUP.parse().unwrap(),
+            api_origins: DEFAULT_API_ORIGINS.iter().map(|origin| origin.to_string()).collect(),
+            share_dirs: vec![],
+        }
+    }
+}
+
+impl LayeredConfig for PeerConfig {
+    const OPTIONS: &'static [&'static str] = &["name", "tracker_url", "trackers", "bind_host", "advertise_host", "port", "data_dir", "scheduler", "max_connections", "connection_policy", "chunk_size", "chunking", "upload_limit", "download_limit", "peer_upload_limit", "peer_download_limit", "upload_slots", "unchoke", "dht", "dht_bootstrap", "lan", "lan_group", "api_origins", "share_dirs"];
+    const ENV_PREFIX: &'static str = "P2P_";
+    const DEFAULT_FILE: &'static str = "p2p_config.json";
+
+    fn apply(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "name" => self.name = Some(value.to_string()),
+            "tracker_url" => self.tracker_url = normalize_url(value),
+            "trackers" => self.trackers = value.split(';')
+                .map(|tier| tier.split(',').map(str::trim).filter(|url| !url.is_empty()).map(normalize_url).collect::<Vec<_>>())
+                .filter(|tier| !tier.is_empty())
+                .collect(),
+            "bind_host" => self.bind_host = value.to_string(),
+            "advertise_host" => self.advertise_host = Some(value.to_string()),
+            "port" => self.port = Some(parse_port(value)?),
+            "data_dir" => self.data_dir = Some(value.to_string()),
+            "scheduler" if SCHEDULERS.contains(&value) => self.scheduler = value.to_string(),
+            "scheduler" => return Err(format!("Estratégia de download inválida: '{}' (use {})", value, SCHEDULERS.join(" ou "))),
+            "max_connections" => self.max_connections = match value.parse::<usize>() {
+                Ok(n) if n >= 1 => n,
+                _ => return Err(format!("Limite de conexões inválido: '{}'", value)),
+            },
+            "connection_policy" if CONNECTION_POLICIES.contains(&value) => self.connection_policy = value.to_string(),
+            "connection_policy" => return Err(format!("Política de conexões inválida: '{}' (use {})", value, CONNECTION_POLICIES.join(" ou "))),
+            "chunk_size" if value == "auto" => self.chunk_size = None,
+            "chunk_size" => self.chunk_size = Some(chunking::parse_chunk_size(value)?),
+            "chunking" if CHUNKING_MODES.contains(&value) => self.chunking = value.to_string(),
+            "chunking" => return Err(format!("Modo de divisão inválido: '{}' (use {})", value, CHUNKING_MODES.join(" ou "))),
+            "upload_limit" => self.upload_limit = throttle::parse_rate(value)?,
+            "download_limit" => self.download_limit = throttle::parse_rate(value)?,
+            "peer_upload_limit" => self.peer_upload_limit = throttle::parse_rate(value)?,
+            "peer_download_limit" => self.peer_download_limit = throttle::parse_rate(value)?,
+            "upload_slots" => self.upload_slots = match value.parse::<usize>() {
+                Ok(n) if n >= 1 => n,
+                _ => return Err(format!("Número de vagas de upload inválido: '{}'", value)),
+            },
+            "unchoke" if UNCHOKE_STRATEGIES.contains(&value) => self.unchoke = value.to_string(),
+            "unchoke" => return Err(format!("Estratégia de unchoke inválida: '{}' (use {})", value, UNCHOKE_STRATEGIES.join(" ou "))),
+            "dht" if DHT_MODES.contains(&value) => self.dht = value.to_string(),
+            "dht" => return Err(format!("Modo de DHT inválido: '{}' (use {})", value, DHT_MODES.join(", "))),
+            "dht_bootstrap" => self.dht_bootstrap = value.split(',')
+                .map(|address| address.trim().to_string())
+                .filter(|address| !address.is_empty())
+                .collect(),
+            "lan" => self.lan = parse_switch(value)?,
+            "lan_group" => self.lan_group = match value.parse::<SocketAddrV4>() {
+                Ok(group) if group.ip().is_multicast() => group,
+                _ => return Err(format!("Grupo multicast inválido: '{}' (ex: {})", value, DEFAULT_LAN_GROUP)),
+            },
+            "api_origins" => self.api_origins = parse_list(value),
+            "share_dirs" => self.share_dirs = parse_list(value),
+            _ => return Err(format!("Opção desconhecida: '{}'", key)),
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        // Valores vindos do arquivo passam pelas mesmas validações das flags e do ambiente
+        let mut checked = vec![
+            ("scheduler", self.scheduler.clone()),
+            ("max_connections", self.max_connections.to_string()),
+            ("connection_policy", self.connection_policy.clone()),
+            ("chunking", self.chunking.clone()),
+            ("upload_slots", self.upload_slots.to_string()),
+            ("unchoke", self.unchoke.clone()),
+            ("dht", self.dht.clone()),
+            ("lan_group", self.lan_group.to_string()),
+        ];
+        if let Some(chunk_size) = self.chunk_size {
+            checked.push(("chunk_size", chunk_size.to_string()));
+        }
+        for (key, value) in checked {
+            self.apply(key, &value)?;
+        }
+
+        self.tracker_url = normalize_url(&self.tracker_url).trim_end_matches('/').to_string();
+        for url in self.trackers.iter_mut().flatten() {
+            *url = normalize_url(url).trim_end_matches('/').to_string();
+        }
+        for origin in self.api_origins.iter_mut() {
+            *origin = origin.trim_end_matches('/').to_string();
+        }
+        Ok(())
+    }
+}
+
+impl PeerConfig {
+    /// Endereço onde o servidor do peer vai escutar
+    pub fn bind_address(&self, port: u16) -> String {
+        format!("{}:{}", self.bind_host, port)
+    }
+
+    /// Níveis de trackers: `trackers` se configurado, senão só `tracker_url`
+    pub fn tracker_tiers(&self) -> Vec<Vec<String>> {
+        if self.trackers.is_empty() {
+            vec![vec![self.tracker_url.clone()]]
+        } else {
+            self.trackers.clone()
+        }
+    }
+
+    /// Limites de banda iniciais do peer
+    pub fn rate_limits(&self) -> RateLimits {
+        RateLimits {
+            upload: self.upload_limit,
+            download: self.download_limit,
+            peer_upload: self.peer_upload_limit,
+            peer_download: self.peer_download_limit,
+        }
+    }
+
+    /// Endereço que o tracker repassa aos outros peers
+    ///
+    /// Sem `advertise_host`, usa o `bind_host`, exceto quando ele é `0.0.0.0`,
+    /// que não é alcançável por outros peers.
+    pub fn advertised_address(&self, port: u16) -> String {
+        format!("{}:{}", self.advertised_host(), port)
+    }
+
+    /// Host anunciado aos outros peers (também a interface dos beacons da rede local)
+    pub fn advertised_host(&self) -> &str {
+        match &self.advertise_host {
+            Some(host) => host.as_str(),
+            None if self.bind_host == "0.0.0.0" => "127.0.0.1",
+            None => self.bind_host.as_str(),
+        }
+    }
+}
+
+/// **Configuração do tracker**
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackerConfig {
+    pub bind_host: String,    // Interface onde o tracker escuta
+    pub port: u16,            // Porta do tracker
+    pub store: String,        // Backend de persistência: "journal" ou "memory"
+    pub journal_path: String, // Arquivo do journal quando `store = "journal"`
+    pub federation: Vec<String>,  // URLs dos trackers federados com este
+    pub federation_interval: u64, // Intervalo (em segundos) entre as replicações
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        TrackerConfig {
+            bind_host: "0.0.0.0".to_string(),
+            port: DEFAULT_TRACKER_PORT,
+            store: "journal".to_string(),
+            journal_path: "tracker_journal.jsonl".to_string(),
+            federation: vec![],
+            federation_interval: 30,
+        }
+    }
+}
+
+impl LayeredConfig for TrackerConfig {
+    const OPTIONS: &'static [&'static str] = &["bind_host", "port", "store", "journal_path", "federation", "federation_interval"];
+    const ENV_PREFIX: &'static str = "P2P_TRACKER_";
+    const DEFAULT_FILE: &'static str = "tracker_config.json";
+
+    fn apply(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "bind_host" => self.bind_host = value.to_string(),
+            "port" => self.port = parse_port(value)?,
+            "store" => match value {
+                "journal" | "memory" => self.store = value.to_string(),
+                _ => return Err(format!("Backend de persistência inválido: '{}' (use journal ou memory)", value)),
+            },
+            "journal_path" => self.journal_path = value.to_string(),
+            "federation" => self.federation = value.split(',')
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .map(normalize_url)
+                .collect(),
+            "federation_interval" => self.federation_interval = match value.parse::<u64>() {
+                Ok(secs) if secs >= 1 => secs,
+                _ => return Err(format!("Intervalo de federação inválido: '{}'", value)),
+            },
+            _ => return Err(format!("Opção desconhecida: '{}'", key)),
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        // Valores vindos do arquivo passam pelas mesmas validações das flags e do ambiente
+        for (key, value) in [("store", self.store.clone()), ("federation_interval", self.federation_interval.to_string())] {
+            self.apply(key, &value)?;
+        }
+
+        for url in self.federation.iter_mut() {
+            *url = normalize_url(url).trim_end_matches('/').to_string();
+        }
+        Ok(())
+    }
+}
+
+impl TrackerConfig {
+    /// Endereço onde o tracker vai escutar
+    pub fn bind_address(&self) -> String {
+        format!("{}:{}", self.bind_host, self.port)
+    }
+}
+
+/// Converte `host:porta` em URL, mantendo URLs que já têm esquema
+fn normalize_url(value: &str) -> String {
+    if value.starts_with("http://") || value.starts_with("https://") {
+        value.to_string()
+    } else {
+        format!("http://{}", value)
+    }
+}
+
+fn parse_port(value: &str) -> Result<u16, String> {
+    value.parse::<u16>().map_err(|_| format!("Porta inválida: '{}'", value))
+}
+
+/// Lê uma lista separada por vírgulas, ignorando itens vazios
+fn parse_list(value: &str) -> Vec<String> {
+    value.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Lê uma opção liga/desliga (`on`/`off`, `true`/`false`, `1`/`0`)
+fn parse_switch(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "on" | "true" | "1" | "yes" => Ok(true),
+        "off" | "false" | "0" | "no" => Ok(false),
+        _ => Err(format!("Valor inválido: '{}' (use on ou off)", value)),
+    }
+}
+
+/// Caminho do arquivo de configuração: `--config`, `{ENV_PREFIX}CONFIG` ou o arquivo padrão se existir
+fn config_file_path<T: LayeredConfig>(args: &CliArgs) -> Option<String> {
+    if let Some(path) = args.get("config") {
+        return Some(path.to_string());
+    }
+    if let Ok(path) = env::var(env_name::<T>("config")) {
+        return Some(path);
+    }
+    if std::path::Path::new(T::DEFAULT_FILE).exists() {
+        return Some(T::DEFAULT_FILE.to_string());
+    }
+    None
+}
+
+/// Nome da variável de ambiente de uma opção
+fn env_name<T: LayeredConfig>(key: &str) -> String {
+    format!("{}{}", T::ENV_PREFIX, key.to_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Configuração lida de um JSON, como em `load`, já com `finish`
+    fn from_file<T: LayeredConfig>(json: &str) -> Result<T, String> {
+        let mut config: T = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        config.finish()?;
+        Ok(config)
+    }
+
+    #[test]
+    fn file_values_are_validated() {
+        for json in [
+            r#"{"max_connections": 0}"#,
+            r#"{"upload_slots": 0}"#,
+            r#"{"scheduler": "fastest"}"#,
+            r#"{"connection_policy": "always"}"#,
+            r#"{"chunking": "rabin"}"#,
+            r#"{"dht": "on"}"#,
+            r#"{"unchoke": "random"}"#,
+            r#"{"lan_group": "10.0.0.1:7645"}"#,
+            r#"{"chunk_size": 10}"#,
+        ] {
+            assert!(from_file::<PeerConfig>(json).is_err(), "{}", json);
+        }
+        for json in [r#"{"federation_interval": 0}"#, r#"{"store": "sqlite"}"#] {
+            assert!(from_file::<TrackerConfig>(json).is_err(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn file_urls_are_normalized() {
+        let config: PeerConfig = from_file(r#"{"tracker_url": "10.0.0.5:9500/", "chunk_size": 65536}"#).unwrap();
+        assert_eq!(config.tracker_url, "http://10.0.0.5:9500");
+        assert_eq!(config.chunk_size, Some(65536));
+        assert!(from_file::<PeerConfig>("{}").is_ok());
+        assert!(from_file::<TrackerConfig>("{}").is_ok());
+    }
+}
@@ -0,0 +1,582 @@
+use axum::{
+    extract::{ConnectInfo, Json, State},
+    http::StatusCode,
+    routing::post,
+    Router,
+};
+use reqwest::Client;
+use serde::{de::DeserializeOwned, Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+use tokio::time::{timeout, Duration, Instant};
+
+use crate::peer::{address_matches, SharedState};
+
+/// Modos aceitos por `--dht`
+pub const DHT_MODES: &[&str] = &["off", "fallback", "only"];
+
+/// Contatos por bucket da tabela de roteamento (o `k` do Kademlia)
+const K: usize = 8;
+
+/// Consultas em paralelo em cada passo de uma busca (o `α` do Kademlia)
+const ALPHA: usize = 3;
+
+/// Validade de um registro "arquivo -> endereço" sem ser republicado
+const RECORD_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// Intervalo entre as republicações dos arquivos locais
+pub const REPUBLISH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Peers guardados por arquivo; um registro novo para um arquivo cheio é recusado
+const MAX_RECORDS_PER_KEY: usize = 20;
+
+/// Registros guardados para os endereços de um mesmo IP, somando todos os arquivos
+///
+/// O limite é por IP, e não por endereço, porque trocar de porta não custa nada.
+const MAX_RECORDS_PER_HOST: usize = 1024;
+
+/// Tempo máximo de espera por uma resposta de outro nó
+const RPC_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Identificador de um nó ou chave na DHT (256 bits, como os IDs de arquivo)
+type NodeId = [u8; 32];
+
+/// **Como a DHT é usada pelo peer**
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DhtMode {
+    /// Sem DHT: só o tracker
+    #[default]
+    Off,
+    /// Tracker primeiro; a DHT é consultada quando ele falha ou não conhece fontes
+    Fallback,
+    /// Sem tracker: descoberta só pela DHT
+    Only,
+}
+
+impl DhtMode {
+    pub fn from_name(name: &str) -> Option<DhtMode> {
+        match name {
+            "off" => Some(DhtMode::Off),
+            "fallback" => Some(DhtMode::Fallback),
+            "only" => Some(DhtMode::Only),
+            _ => None,
+        }
+    }
+}
+
+/// ID de um nó: SHA-256 do endereço anunciado, para que ninguém escolha a própria posição na DHT
+///
+/// O endereço só é aceito quando o seu host é o IP da conexão (ver `store`).
+fn node_id(address: &str) -> NodeId {
+    Sha256::digest(address.as_bytes()).into()
+}
+
+/// Converte um ID de arquivo (hexadecimal) em chave da DHT
+fn parse_key(key: &str) -> Option<NodeId> {
+    if !crate::manifest::is_file_id(key) {
+        return None;
+    }
+    let mut id = [0u8; 32];
+    for (i, byte) in id.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&key[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(id)
+}
+
+/// Distância XOR entre dois IDs; comparar os arrays compara as distâncias
+fn distance(a: &NodeId, b: &NodeId) -> NodeId {
+    let mut result = [0u8; 32];
+    for (byte, (x, y)) in result.iter_mut().zip(a.iter().zip(b)) {
+        *byte = x ^ y;
+    }
+    result
+}
+
+/// Bucket de um contato: quantidade de bits iniciais em comum com o próprio ID
+fn bucket_index(own: &NodeId, other: &NodeId) -> Option<usize> {
+    let distance = distance(own, other);
+    let byte = distance.iter().position(|byte| *byte != 0)?;
+    Some(byte * 8 + distance[byte].leading_zeros() as usize)
+}
+
+/// **Tabela de roteamento**: até `K` endereços por bucket, dos mais antigos aos mais recentes
+///
+/// Um bucket cheio mantém os contatos antigos (que continuam respondendo) e ignora os novos;
+/// contatos que deixam de responder são removidos e abrem espaço.
+struct RoutingTable {
+    own: NodeId,
+    buckets: Vec<Vec<String>>,
+}
+
+impl RoutingTable {
+    fn new(own: NodeId) -> Self {
+        RoutingTable { own, buckets: vec![vec![]; 256] }
+    }
+
+    fn insert(&mut self, address: &str) {
+        let Some(index) = bucket_index(&self.own, &node_id(address)) else {
+            return; // O próprio nó
+        };
+        let bucket = &mut self.buckets[index];
+        if let Some(position) = bucket.iter().position(|contact| contact == address) {
+            let contact = bucket.remove(position);
+            bucket.push(contact);
+        } else if bucket.len() < K {
+            bucket.push(address.to_string());
+        }
+    }
+
+    fn remove(&mut self, address: &str) {
+        if let Some(index) = bucket_index(&self.own, &node_id(address)) {
+            self.buckets[index].retain(|contact| contact != address);
+        }
+    }
+
+    /// Os `count` contatos mais próximos de `target`
+    fn closest(&self, target: &NodeId, count: usize) -> Vec<String> {
+        let mut contacts: Vec<&String> = self.buckets.iter().flatten().collect();
+        contacts.sort_by_key(|contact| distance(&node_id(contact), target));
+        contacts.into_iter().take(count).cloned().collect()
+    }
+
+    fn contains(&self, address: &str) -> bool {
+        bucket_index(&self.own, &node_id(address)).is_some_and(|index| self.buckets[index].iter().any(|contact| contact == address))
+    }
+
+    fn len(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.len()).sum()
+    }
+}
+
+/// Mensagens trocadas entre os nós (`POST /dht/...`)
+///
+/// Toda mensagem leva o endereço de quem envia, que entra na tabela de roteamento de quem recebe
+/// se o seu host for o IP da conexão.
+#[derive(Debug, Serialize, Deserialize)]
+struct PingRequest {
+    sender: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FindRequest {
+    sender: String,
+    key: String, // ID procurado (nó ou arquivo)
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct FindResponse {
+    contacts: Vec<String>, // Nós mais próximos da chave que quem respondeu conhece
+    values: Vec<String>,   // Peers com o arquivo (só em `find_value`)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoreRequest {
+    sender: String, // Peer que tem o arquivo
+    key: String,    // ID do arquivo
+}
+
+/// **Nó da DHT no estilo Kademlia**
+///
+/// Guarda registros "ID do arquivo -> endereços dos peers que o têm" nos nós cujos IDs
+/// estão mais próximos (pela distância XOR) do ID do arquivo. Os nós se comunicam pelo
+/// mesmo servidor HTTP do peer, e o ID de cada nó é o SHA-256 do seu endereço.
+///
+/// Um nó só consegue anunciar o próprio endereço, mas nada impede que ele se diga dono de
+/// arquivos que não tem, nem que vários endereços de uma mesma máquina (portas diferentes)
+/// ocupem a região de uma chave: os registros são só pistas, e os chunks de quem não tem o
+/// arquivo falham na verificação de hash e fazem o peer trocar de fonte. A memória gasta com
+/// registros de terceiros é limitada por arquivo e por IP (`MAX_RECORDS_PER_KEY` e
+/// `MAX_RECORDS_PER_HOST`).
+///
+/// O endereço anunciado precisa ser `IP:porta`: um nome de host (`--advertise-host` com nome)
+/// não é resolvido por quem recebe a mensagem, e o nó fica de fora das tabelas dos outros.
+pub struct Dht {
+    pub mode: DhtMode,
+    address: String,
+    id: NodeId,
+    bootstrap: Vec<String>,
+    table: Mutex<RoutingTable>,
+    records: Mutex<HashMap<String, HashMap<String, Instant>>>,
+    client: Client,
+}
+
+impl Dht {
+    pub fn new(mode: DhtMode, address: &str, bootstrap: Vec<String>) -> Self {
+        if address.parse::<SocketAddr>().is_err() {
+            println!("⚠️ DHT: o endereço anunciado '{}' não é IP:porta; os outros nós vão recusar as mensagens deste peer. Use um IP em --advertise-host.", address);
+        }
+        let id = node_id(address);
+        Dht {
+            mode,
+            address: address.to_string(),
+            id,
+            bootstrap,
+            table: Mutex::new(RoutingTable::new(id)),
+            records: Mutex::new(HashMap::new()),
+            client: Client::new(),
+        }
+    }
+
+    /// Quantidade de nós conhecidos
+    pub fn node_count(&self) -> usize {
+        self.table.lock().unwrap().len()
+    }
+
+    /// **Entra na rede**: contata os nós da lista de bootstrap e procura o próprio ID
+    pub async fn bootstrap(&self) {
+        for address in self.bootstrap.iter().filter(|address| **address != self.address) {
+            let request = PingRequest { sender: self.address.clone() };
+            if self.rpc::<_, PingRequest>(address, "ping", &request).await.is_none() {
+                println!("⚠️ Nó de bootstrap '{}' da DHT não respondeu", address);
+            }
+        }
+        self.lookup(&self.id, false).await;
+        println!("🌐 DHT: {} nó(s) conhecido(s)", self.node_count());
+    }
+
+    /// **Anuncia que este peer tem o arquivo `file_id`** nos nós mais próximos do ID
+    pub async fn announce(&self, file_id: &str) {
+        let Some(key) = parse_key(file_id) else {
+            return;
+        };
+        self.store_record(file_id, &self.address);
+
+        let (closest, _) = self.lookup(&key, false).await;
+        let request = StoreRequest { sender: self.address.clone(), key: file_id.to_string() };
+        let stores = closest.iter().map(|address| self.rpc::<_, String>(address, "store", &request));
+        let stored = futures::future::join_all(stores).await.into_iter().flatten().count();
+        println!("🌐 DHT: '{}' anunciado em {} nó(s)", file_id, stored);
+    }
+
+    /// **Peers que anunciaram o arquivo `file_id`**
+    pub async fn find_peers(&self, file_id: &str) -> Vec<String> {
+        let Some(key) = parse_key(file_id) else {
+            return vec![];
+        };
+        let (_, values) = self.lookup(&key, true).await;
+
+        let mut peers: Vec<String> = self.local_values(file_id);
+        for value in values {
+            if !peers.contains(&value) {
+                peers.push(value);
+            }
+        }
+        peers.retain(|peer| *peer != self.address);
+        peers
+    }
+
+    /// Remove os registros vencidos
+    pub fn expire_records(&self) {
+        let mut records = self.records.lock().unwrap();
+        for peers in records.values_mut() {
+            peers.retain(|_, stored| stored.elapsed() <= RECORD_TTL);
+        }
+        records.retain(|_, peers| !peers.is_empty());
+    }
+
+    fn store_record(&self, file_id: &str, address: &str) {
+        self.records.lock().unwrap()
+            .entry(file_id.to_string())
+            .or_default()
+            .insert(address.to_string(), Instant::now());
+    }
+
+    /// **Guarda o registro de outro nó**, respeitando os limites por arquivo e por IP
+    ///
+    /// Renovar um registro existente é sempre aceito; registros vencidos não contam.
+    fn accept_record(&self, file_id: &str, address: &str, host: IpAddr) -> Result<(), String> {
+        let mut records = self.records.lock().unwrap();
+        let live = |stored: &Instant| stored.elapsed() <= RECORD_TTL;
+
+        let known = records.get(file_id).and_then(|peers| peers.get(address)).is_some_and(live);
+        if !known {
+            let per_key = records.get(file_id).map_or(0, |peers| peers.values().filter(|stored| live(stored)).count());
+            if per_key >= MAX_RECORDS_PER_KEY {
+                return Err(format!("o arquivo '{}' já tem {} registros", file_id, MAX_RECORDS_PER_KEY));
+            }
+            let host = host.to_canonical();
+            let per_host = records.values()
+                .flat_map(|peers| peers.iter())
+                .filter(|(peer, stored)| live(stored) && peer.parse::<SocketAddr>().is_ok_and(|peer| peer.ip().to_canonical() == host))
+                .count();
+            if per_host >= MAX_RECORDS_PER_HOST {
+                return Err(format!("{} já tem {} registros", host, MAX_RECORDS_PER_HOST));
+            }
+        }
+
+        records.entry(file_id.to_string()).or_default().insert(address.to_string(), Instant::now());
+        Ok(())
+    }
+
+    fn local_values(&self, file_id: &str) -> Vec<String> {
+        self.records.lock().unwrap()
+            .get(file_id)
+            .map(|peers| peers.iter().filter(|(_, stored)| stored.elapsed() <= RECORD_TTL).map(|(peer, _)| peer.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// **Busca iterativa**: consulta os nós cada vez mais próximos de `target`
+    ///
+    /// Retorna os `K` nós mais próximos encontrados e, em `find_value`, os peers com o arquivo.
+    async fn lookup(&self, target: &NodeId, find_value: bool) -> (Vec<String>, Vec<String>) {
+        let mut shortlist = self.table.lock().unwrap().closest(target, K);
+        if shortlist.is_empty() {
+            shortlist = self.bootstrap.iter().filter(|address| **address != self.address).cloned().collect();
+        }
+        let key = hex(target);
+        let mut queried: HashSet<String> = HashSet::new();
+        let mut values: Vec<String> = vec![];
+
+        loop {
+            shortlist.sort_by_key(|contact| distance(&node_id(contact), target));
+            shortlist.dedup();
+            let round: Vec<String> = shortlist.iter()
+                .take(K)
+                .filter(|contact| !queried.contains(*contact))
+                .take(ALPHA)
+                .cloned()
+                .collect();
+            if round.is_empty() {
+                break;
+            }
+
+            let method = if find_value { "find_value" } else { "find_node" };
+            let request = FindRequest { sender: self.address.clone(), key: key.clone() };
+            let replies = futures::future::join_all(round.iter().map(|contact| self.rpc::<_, FindResponse>(contact, method, &request))).await;
+            queried.extend(round);
+
+            for reply in replies.into_iter().flatten() {
+                for value in reply.values {
+                    if !values.contains(&value) {
+                        values.push(value);
+                    }
+                }
+                for contact in reply.contacts {
+                    if contact != self.address && !shortlist.contains(&contact) {
+                        shortlist.push(contact);
+                    }
+                }
+            }
+            if find_value && !values.is_empty() {
+                break;
+            }
+        }
+
+        // Só entram no resultado os nós que responderam
+        let table = self.table.lock().unwrap();
+        shortlist.retain(|contact| queried.contains(contact) && table.contains(contact));
+        shortlist.truncate(K);
+        (shortlist, values)
+    }
+
+    /// Envia uma mensagem a outro nó; quem responde entra na tabela e quem não responde sai
+    async fn rpc<T: Serialize, R: DeserializeOwned>(&self, address: &str, method: &str, request: &T) -> Option<R> {
+        let url = format!("http://{}/dht/{}", address, method);
+        let call = async {
+            self.client.post(&url).json(request).send().await?.error_for_status()?.json::<R>().await
+        };
+        match timeout(RPC_TIMEOUT, call).await {
+            Ok(Ok(response)) => {
+                self.table.lock().unwrap().insert(address);
+                Some(response)
+            }
+            _ => {
+                self.table.lock().unwrap().remove(address);
+                None
+            }
+        }
+    }
+
+    /// Registra o remetente de uma mensagem recebida na tabela de roteamento
+    fn observe(&self, sender: &str) {
+        if sender != self.address {
+            self.table.lock().unwrap().insert(sender);
+        }
+    }
+}
+
+/// Texto hexadecimal de um ID
+fn hex(id: &NodeId) -> String {
+    id.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// **Rotas da DHT (`/dht/...`)**, usadas pelos outros nós
+pub fn routes() -> Router<SharedState> {
+    Router::new()
+        .route("/dht/ping", post(ping))
+        .route("/dht/find_node", post(find_node))
+        .route("/dht/find_value", post(find_value))
+        .route("/dht/store", post(store))
+}
+
+/// Indica se o remetente de uma mensagem é o IP da conexão, registrando o motivo quando não é
+///
+/// Nomes de host não são resolvidos, para que uma mensagem não faça este nó consultar o DNS
+/// em nome de terceiros.
+fn sender_matches(sender: &str, remote: SocketAddr) -> bool {
+    if address_matches(sender, remote) {
+        return true;
+    }
+    if sender.parse::<SocketAddr>().is_err() {
+        println!("⚠️ DHT: remetente '{}' ignorado: só endereços IP:porta são aceitos", sender);
+    } else {
+        println!("⚠️ DHT: remetente '{}' ignorado: não confere com a conexão de {}", sender, remote.ip());
+    }
+    false
+}
+
+/// Nó da DHT do peer, ou `404` se a DHT estiver desativada
+fn node(state: &SharedState) -> Result<&Dht, (StatusCode, Json<String>)> {
+    state.dht.as_ref().ok_or((StatusCode::NOT_FOUND, Json("DHT desativada neste peer".to_string())))
+}
+
+async fn ping(
+    State(state): State<SharedState>,
+    ConnectInfo(remote): ConnectInfo<SocketAddr>,
+    Json(request): Json<PingRequest>,
+) -> Result<Json<PingRequest>, (StatusCode, Json<String>)> {
+    let dht = node(&state)?;
+    if sender_matches(&request.sender, remote) {
+        dht.observe(&request.sender);
+    }
+    Ok(Json(PingRequest { sender: dht.address.clone() }))
+}
+
+async fn find_node(
+    State(state): State<SharedState>,
+    ConnectInfo(remote): ConnectInfo<SocketAddr>,
+    Json(request): Json<FindRequest>,
+) -> Result<Json<FindResponse>, (StatusCode, Json<String>)> {
+    let dht = node(&state)?;
+    let Some(target) = parse_key(&request.key) else {
+        return Err((StatusCode::BAD_REQUEST, Json(format!("Chave inválida: '{}'", request.key))));
+    };
+    if sender_matches(&request.sender, remote) {
+        dht.observe(&request.sender);
+    }
+    let contacts = dht.table.lock().unwrap().closest(&target, K);
+    Ok(Json(FindResponse { contacts, values: vec![] }))
+}
+
+async fn find_value(
+    State(state): State<SharedState>,
+    ConnectInfo(remote): ConnectInfo<SocketAddr>,
+    Json(request): Json<FindRequest>,
+) -> Result<Json<FindResponse>, (StatusCode, Json<String>)> {
+    let dht = node(&state)?;
+    let Some(target) = parse_key(&request.key) else {
+        return Err((StatusCode::BAD_REQUEST, Json(format!("Chave inválida: '{}'", request.key))));
+    };
+    if sender_matches(&request.sender, remote) {
+        dht.observe(&request.sender);
+    }
+    let contacts = dht.table.lock().unwrap().closest(&target, K);
+    Ok(Json(FindResponse { contacts, values: dht.local_values(&request.key) }))
+}
+
+async fn store(
+    State(state): State<SharedState>,
+    ConnectInfo(remote): ConnectInfo<SocketAddr>,
+    Json(request): Json<StoreRequest>,
+) -> Result<Json<String>, (StatusCode, Json<String>)> {
+    let dht = node(&state)?;
+    if parse_key(&request.key).is_none() {
+        return Err((StatusCode::BAD_REQUEST, Json(format!("Chave inválida: '{}'", request.key))));
+    }
+    // Sem essa conferência, qualquer um poderia anunciar endereços de terceiros como donos do arquivo
+    if !sender_matches(&request.sender, remote) {
+        return Err((StatusCode::FORBIDDEN, Json(format!("O remetente '{}' não confere com a conexão", request.sender))));
+    }
+    dht.observe(&request.sender);
+    if let Err(reason) = dht.accept_record(&request.key, &request.sender, remote.ip()) {
+        println!("⚠️ DHT: registro de '{}' recusado: {}", request.sender, reason);
+        return Err((StatusCode::TOO_MANY_REQUESTS, Json(format!("Registro recusado: {}", reason))));
+    }
+    Ok(Json("Registro guardado".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PeerConfig;
+    use crate::peer::build_state;
+
+    fn key(index: usize) -> String {
+        format!("{:064x}", index)
+    }
+
+    /// Sobe um peer com a DHT ligada em 127.0.0.1, numa porta livre
+    async fn spawn_node(name: &str, bootstrap: Vec<String>) -> SharedState {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let config = PeerConfig { dht: "only".to_string(), dht_bootstrap: bootstrap, ..Default::default() };
+        let state = build_state(&config, name, &address);
+        let app = routes().with_state(state.clone());
+        tokio::spawn(async move {
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await
+        });
+        state
+    }
+
+    #[tokio::test]
+    async fn announced_file_is_found_through_other_nodes() {
+        let alice = spawn_node("alice", vec![]).await;
+        let bob = spawn_node("bob", vec![alice.address.clone()]).await;
+        let carol = spawn_node("carol", vec![bob.address.clone()]).await;
+        for node in [&alice, &bob, &carol] {
+            node.dht.as_ref().unwrap().bootstrap().await;
+        }
+
+        // Carol só conhece Bob, mas chega a Alice pelas buscas
+        assert!(carol.dht.as_ref().unwrap().node_count() >= 2);
+        alice.dht.as_ref().unwrap().announce(&key(7)).await;
+        assert_eq!(carol.dht.as_ref().unwrap().find_peers(&key(7)).await, vec![alice.address.clone()]);
+        assert!(carol.dht.as_ref().unwrap().find_peers(&key(8)).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn store_for_another_host_is_refused() {
+        let alice = spawn_node("alice", vec![]).await;
+        let request = StoreRequest { sender: "10.0.0.9:8000".to_string(), key: key(7) };
+        let response = Client::new()
+            .post(format!("http://{}/dht/store", alice.address))
+            .json(&request)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status().as_u16(), StatusCode::FORBIDDEN.as_u16());
+        assert!(alice.dht.as_ref().unwrap().local_values(&key(7)).is_empty());
+    }
+
+    #[test]
+    fn records_per_key_are_limited() {
+        let dht = Dht::new(DhtMode::Only, "127.0.0.1:9000", vec![]);
+        let host: IpAddr = "10.0.0.1".parse().unwrap();
+        for port in 0..MAX_RECORDS_PER_KEY {
+            dht.accept_record(&key(1), &format!("10.0.0.1:{}", 7000 + port), host).unwrap();
+        }
+
+        assert!(dht.accept_record(&key(1), "10.0.0.1:8000", host).is_err());
+        // Quem já está guardado continua podendo renovar, e outros arquivos não são afetados
+        assert!(dht.accept_record(&key(1), "10.0.0.1:7000", host).is_ok());
+        assert!(dht.accept_record(&key(2), "10.0.0.1:8000", host).is_ok());
+        assert_eq!(dht.local_values(&key(1)).len(), MAX_RECORDS_PER_KEY);
+    }
+
+    #[test]
+    fn records_per_host_are_limited() {
+        let dht = Dht::new(DhtMode::Only, "127.0.0.1:9000", vec![]);
+        let host: IpAddr = "10.0.0.2".parse().unwrap();
+        for index in 0..MAX_RECORDS_PER_HOST {
+            let port = 7000 + index % 2; // Trocar de porta não escapa do limite
+            dht.accept_record(&key(index), &format!("10.0.0.2:{}", port), host).unwrap();
+        }
+
+        assert!(dht.accept_record(&key(MAX_RECORDS_PER_HOST), "10.0.0.2:7002", host).is_err());
+        let other: IpAddr = "10.0.0.3".parse().unwrap();
+        assert!(dht.accept_record(&key(MAX_RECORDS_PER_HOST), "10.0.0.3:7000", other).is_ok());
+    }
+}
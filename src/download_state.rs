@@ -0,0 +1,146 @@
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::manifest::FileManifest;
+use crate::piece_store::Bitfield;
+use crate::storage::Storage;
+
+/// Sufixo dos arquivos de estado de download em `downloads/`
+const MANIFEST_SUFFIX: &str = ".download.json";
+
+/// **Progresso de um chunk dentro de um download**
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkProgress {
+    pub checksum: String,                // Checksum esperado do chunk
+    pub sources: Vec<String>,            // Peers que anunciaram o chunk
+    pub downloaded_from: Option<String>, // Peer de onde o chunk veio
+}
+
+/// **Estado persistido de um download, usado para retomá-lo após uma interrupção**
+///
+/// Fica em `downloads/<id>.download.json` e é regravado a cada chunk verificado.
+/// Os chunks esperados e seus checksums vêm do manifesto do arquivo, não do tracker;
+/// os dados ficam em `downloads/<id>.part` e `pieces` indica quais peças já foram gravadas.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadManifest {
+    pub file: FileManifest,                      // Manifesto do arquivo sendo baixado
+    pub max_connections: usize,
+    pub chunks: BTreeMap<String, ChunkProgress>, // Chunks esperados, por nome
+    pub pieces: Bitfield,                        // Peças já gravadas e verificadas no arquivo parcial
+}
+
+impl DownloadManifest {
+    pub fn new(file: FileManifest, max_connections: usize) -> Self {
+        let chunks = file.chunks()
+            .map(|(chunk_name, hash)| (chunk_name, ChunkProgress {
+                checksum: hash.clone(),
+                sources: vec![],
+                downloaded_from: None,
+            }))
+            .collect();
+
+        let pieces = Bitfield::new(file.chunk_count());
+        DownloadManifest { file, max_connections, chunks, pieces }
+    }
+
+    pub fn file_id(&self) -> &str {
+        &self.file.id
+    }
+
+    fn path(storage: &Storage, file_id: &str) -> PathBuf {
+        storage.download_path(&format!("{}{}", file_id, MANIFEST_SUFFIX))
+    }
+
+    /// Carrega o estado de um download em andamento, se existir
+    pub fn load(storage: &Storage, file_id: &str) -> Option<DownloadManifest> {
+        let content = fs::read_to_string(Self::path(storage, file_id)).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(manifest) => Some(manifest),
+            Err(e) => {
+                println!("⚠️ Estado de download de '{}' ilegível, recomeçando: {}", file_id, e);
+                None
+            }
+        }
+    }
+
+    /// Lista todos os downloads não concluídos
+    pub fn pending(storage: &Storage) -> Vec<DownloadManifest> {
+        fs::read_dir(storage.downloads_dir())
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .filter_map(|entry| entry.file_name().to_str().map(|s| s.to_string()))
+                    .filter_map(|name| name.strip_suffix(MANIFEST_SUFFIX).map(|s| s.to_string()))
+                    .filter_map(|file_id| DownloadManifest::load(storage, &file_id))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Grava o estado de forma atômica (arquivo temporário + rename)
+    pub fn save(&self, storage: &Storage) -> io::Result<()> {
+        let path = Self::path(storage, self.file_id());
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&tmp_path, &path)
+    }
+
+    /// Remove o estado de um download concluído
+    pub fn remove(storage: &Storage, file_id: &str) {
+        let _ = fs::remove_file(Self::path(storage, file_id));
+    }
+
+    /// Acrescenta uma fonte para um chunk do manifesto
+    ///
+    /// Anúncios de chunks desconhecidos ou com checksum diferente do manifesto são ignorados.
+    pub fn add_source(&mut self, chunk_name: &str, checksum: &str, peer_address: &str) {
+        let Some(progress) = self.chunks.get_mut(chunk_name) else {
+            return;
+        };
+        if progress.checksum == checksum && !progress.sources.iter().any(|source| source == peer_address) {
+            progress.sources.push(peer_address.to_string());
+        }
+    }
+
+    /// Marca um chunk como gravado e verificado
+    pub fn mark_verified(&mut self, chunk_name: &str, peer_address: Option<&str>) {
+        let Some(index) = self.file.chunk_index(chunk_name) else {
+            return;
+        };
+        self.pieces.set(index);
+        if let Some(progress) = self.chunks.get_mut(chunk_name) {
+            progress.downloaded_from = peer_address.map(|address| address.to_string());
+        }
+    }
+
+    /// Desmarca uma peça que precisa ser baixada de novo
+    pub fn mark_missing(&mut self, index: usize) {
+        self.pieces.clear(index);
+        if let Some(progress) = self.chunks.get_mut(&self.file.chunk_name(index)) {
+            progress.downloaded_from = None;
+        }
+    }
+
+    /// Nomes dos chunks ainda não verificados
+    pub fn missing(&self) -> Vec<String> {
+        (0..self.file.chunk_count())
+            .filter(|index| !self.pieces.get(*index))
+            .map(|index| self.file.chunk_name(index))
+            .collect()
+    }
+
+    /// Nomes dos chunks já gravados e verificados
+    pub fn present(&self) -> Vec<String> {
+        (0..self.file.chunk_count())
+            .filter(|index| self.pieces.get(*index))
+            .map(|index| self.file.chunk_name(index))
+            .collect()
+    }
+
+    pub fn verified_count(&self) -> usize {
+        self.pieces.count()
+    }
+}
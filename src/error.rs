@@ -0,0 +1,84 @@
+use axum::http::StatusCode;
+use std::fmt;
+use std::io;
+
+/// **Erros do peer**
+///
+/// Substitui os `unwrap`/`expect` nos caminhos de compartilhamento e download: cada falha
+/// chega a quem chamou como um `Result` e, na API, vira o código HTTP de `status()`.
+#[derive(Debug)]
+pub enum P2pError {
+    /// Falha de leitura ou escrita em disco (arquivo ausente, disco cheio, ...)
+    Io(io::Error),
+    /// Tracker inacessível ou resposta HTTP ilegível
+    Tracker(reqwest::Error),
+    /// O tracker respondeu com erro
+    TrackerRejected { status: u16, message: String },
+    /// Conteúdo que não confere com o manifesto do arquivo
+    ChecksumMismatch { file: String, detail: String },
+    /// Pedido ou dado fora do protocolo (manifesto inválido, nome ambíguo, ...)
+    Protocol(String),
+    /// Arquivo, chunk ou peer inexistente
+    NotFound(String),
+    /// Nenhum peer disponível para concluir a operação
+    Unavailable(String),
+}
+
+/// Resultado das operações do peer
+pub type P2pResult<T> = Result<T, P2pError>;
+
+impl P2pError {
+    /// Código HTTP usado ao devolver o erro pela API
+    pub fn status(&self) -> StatusCode {
+        match self {
+            P2pError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            P2pError::Tracker(_) | P2pError::TrackerRejected { .. } => StatusCode::BAD_GATEWAY,
+            P2pError::ChecksumMismatch { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            P2pError::Protocol(_) => StatusCode::BAD_REQUEST,
+            P2pError::NotFound(_) => StatusCode::NOT_FOUND,
+            P2pError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    /// Converte uma resposta de erro do tracker, aproveitando a mensagem enviada por ele
+    pub async fn rejected(response: reqwest::Response) -> P2pError {
+        let status = response.status().as_u16();
+        let message: String = response.json().await.unwrap_or_default();
+        P2pError::TrackerRejected { status, message }
+    }
+}
+
+impl fmt::Display for P2pError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            P2pError::Io(e) => write!(f, "erro de disco: {}", e),
+            P2pError::Tracker(e) => write!(f, "erro de comunicação com o Tracker: {}", e),
+            P2pError::TrackerRejected { status, message } if message.is_empty() => write!(f, "o Tracker respondeu HTTP {}", status),
+            P2pError::TrackerRejected { status, message } => write!(f, "o Tracker respondeu HTTP {}: {}", status, message),
+            P2pError::ChecksumMismatch { file, detail } => write!(f, "'{}' não confere com o manifesto: {}", file, detail),
+            P2pError::Protocol(message) | P2pError::NotFound(message) | P2pError::Unavailable(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for P2pError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            P2pError::Io(e) => Some(e),
+            P2pError::Tracker(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for P2pError {
+    fn from(e: io::Error) -> Self {
+        P2pError::Io(e)
+    }
+}
+
+impl From<reqwest::Error> for P2pError {
+    fn from(e: reqwest::Error) -> Self {
+        P2pError::Tracker(e)
+    }
+}
@@ -0,0 +1,84 @@
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures::stream::{self, Stream};
+use serde::Serialize;
+use std::convert::Infallible;
+use tokio::sync::broadcast;
+
+use crate::peer::SharedState;
+
+/// Quantidade de eventos guardados para assinantes lentos antes de descartar os mais antigos
+const EVENT_BUFFER: usize = 256;
+
+/// **Eventos publicados pelo peer para a interface**
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PeerEvent {
+    ChatReceived { sender: String, message: String, timestamp: u64 },
+    ChunkDownloaded { file: String, chunk: String, peer: String, bytes: usize },
+    ChecksumMismatch { file: String, chunk: String, peer: String },
+    DownloadComplete { file: String, bytes: usize, seconds: f64 },
+    FileAssembled { file: String, checksum: String },
+    FileVerificationFailed { file: String, error: String },
+    TrackerUnreachable { tracker_url: String, error: String },
+}
+
+/// **Barramento de eventos do peer (broadcast para todos os assinantes)**
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<PeerEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        EventBus { sender }
+    }
+
+    /// Publica um evento; sem assinantes, o evento é simplesmente descartado
+    pub fn publish(&self, event: PeerEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PeerEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// **Stream de eventos em tempo real via Server-Sent Events (`/api/events`)**
+///
+/// Cada evento é enviado como JSON com o campo `type` e também usa o mesmo nome no campo `event`
+/// do SSE, permitindo `EventSource.addEventListener("chat_received", ...)` no frontend.
+pub async fn stream_events(
+    State(state): State<SharedState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = state.events.subscribe();
+
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((Ok(to_sse(&event)), receiver)),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    println!("⚠️ Assinante de eventos atrasado: {} evento(s) descartado(s)", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+fn to_sse(event: &PeerEvent) -> Event {
+    let data = serde_json::to_value(event).unwrap_or_default();
+    let name = data.get("type").and_then(|t| t.as_str()).unwrap_or("message").to_string();
+    Event::default().event(name).data(data.to_string())
+}
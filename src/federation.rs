@@ -0,0 +1,78 @@
+use reqwest::Client;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use tokio::time::{timeout, Duration};
+
+use crate::tracker::{ChunkRegister, FileRegister, Peer};
+
+/// Tempo máximo de espera pela resposta de um tracker federado
+const FEDERATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// **Saída de um peer**, replicada para que os outros trackers também o removam
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Departure {
+    pub peer: String,
+    pub address: String,
+    pub left_at: u64, // Timestamp da saída, comparado com o `last_seen` de cada tracker
+}
+
+/// **Índices próprios de um tracker**, como trocados entre trackers federados em `/federation/snapshot`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub peers: Vec<Peer>,
+    pub files: Vec<FileRegister>,
+    pub chunks: Vec<ChunkRegister>,
+    pub departures: Vec<Departure>,
+}
+
+/// **Trackers federados com este**
+///
+/// A cada `interval`, o tracker puxa o snapshot de cada tracker federado e o junta aos seus
+/// índices; as consultas que não encontram nada localmente também são repassadas a eles.
+pub struct Federation {
+    client: Client,
+    trackers: Vec<String>,
+    pub interval: Duration,
+}
+
+impl Federation {
+    pub fn new(trackers: Vec<String>, interval: Duration) -> Self {
+        Federation { client: Client::new(), trackers, interval }
+    }
+
+    /// URLs dos trackers federados
+    pub fn trackers(&self) -> &[String] {
+        &self.trackers
+    }
+
+    /// Baixa o snapshot dos índices de um tracker federado
+    pub async fn pull(&self, tracker_url: &str) -> Result<Snapshot, String> {
+        let url = format!("{}/federation/snapshot", tracker_url);
+        let request = async {
+            self.client.get(&url).send().await?.error_for_status()?.json::<Snapshot>().await
+        };
+        match timeout(FEDERATION_TIMEOUT, request).await {
+            Ok(Ok(snapshot)) => Ok(snapshot),
+            Ok(Err(e)) => Err(e.to_string()),
+            Err(_) => Err("tempo esgotado".to_string()),
+        }
+    }
+
+    /// **Repassa uma consulta a todos os trackers federados**
+    ///
+    /// A consulta vai com `local=1`, para que eles respondam só com os próprios índices e não a
+    /// repassem de novo. Trackers que não respondem ficam de fora do resultado.
+    pub async fn query<T: DeserializeOwned>(&self, path: &str, params: &[(&str, &str)]) -> Vec<T> {
+        let requests = self.trackers.iter().map(|tracker_url| async move {
+            let request = async {
+                self.client.get(format!("{}{}", tracker_url, path))
+                    .query(params)
+                    .query(&[("local", "1")])
+                    .send().await?
+                    .error_for_status()?
+                    .json::<T>().await
+            };
+            timeout(FEDERATION_TIMEOUT, request).await.ok()?.ok()
+        });
+        futures::future::join_all(requests).await.into_iter().flatten().collect()
+    }
+}
@@ -1,107 +1,141 @@
-use std::fs::{self, File};
-use std::io::{Read, Write};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
 use sha2::{Sha256, Digest};
+use tokio::task;
+
+use crate::chunking::{Chunker, ChunkingMode, ChunkingOptions};
+use crate::error::{P2pError, P2pResult};
+use crate::manifest::FileManifest;
+
+/// Tamanho do buffer usado para copiar e calcular checksums em streaming
+const BUFFER_SIZE: usize = 64 * 1024;
+
+// As funções públicas são assíncronas: o trabalho de disco e de hash roda em
+// `spawn_blocking`, sem ocupar as threads do runtime, e nunca carrega um arquivo
+// inteiro na memória.
+
+/// Executa uma tarefa de disco em uma thread de bloqueio
+pub async fn blocking<T, F>(work: F) -> P2pResult<T>
+where
+    F: FnOnce() -> P2pResult<T> + Send + 'static,
+    T: Send + 'static,
+{
+    task::spawn_blocking(work).await.map_err(|e| P2pError::Io(io::Error::other(e)))?
+}
 
-const CHUNK_SIZE: usize = 1024 * 1024; // 1MB
+/// **Divide um arquivo em chunks e gera o seu manifesto**
+///
+/// O tamanho dos chunks vem de `options` (ou é escolhido pelo tamanho do arquivo) e, no
+/// modo `cdc`, os cortes são definidos pelo conteúdo. Lê o arquivo em `path` uma única vez,
+/// calculando o hash de cada chunk e o checksum do arquivo inteiro. Nenhuma cópia é
+/// gravada: os chunks são servidos depois por posição, direto do arquivo.
+pub async fn split_file(path: &Path, options: ChunkingOptions) -> P2pResult<FileManifest> {
+    let path = path.to_path_buf();
+    blocking(move || split_file_blocking(&path, options)).await
+}
 
-/// **Divide um arquivo em chunks de 1MB e calcula o checksum**
-pub fn split_file(file_name: &str) -> Vec<(usize, String, String)> {
-    let mut file = File::open(file_name).expect("Erro ao abrir arquivo");
-    let mut buffer = vec![0; CHUNK_SIZE];
-    let mut chunks = vec![];
+fn split_file_blocking(path: &Path, options: ChunkingOptions) -> P2pResult<FileManifest> {
+    let file_name = path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| P2pError::Protocol(format!("'{}' não é um caminho de arquivo", path.display())))?;
+    let file_name = file_name.as_str();
+    let mut file = File::open(path)
+        .map_err(|e| P2pError::NotFound(format!("arquivo '{}' não encontrado: {}", path.display(), e)))?;
+    let chunk_size = options.chunk_size_for(file.metadata()?.len());
+    let mut chunker = Chunker::new(options.mode, chunk_size);
+
+    let mut buffer = vec![0; BUFFER_SIZE];
+    let mut chunk_hashes = vec![];
+    let mut chunk_lengths = vec![];
+    let mut file_hasher = Sha256::new();
+    let mut chunk_hasher = Sha256::new();
+    let mut chunk_len = 0;
+    let mut size = 0u64;
 
-    let mut index = 0;
-    while let Ok(size) = file.read(&mut buffer) {
-        if size == 0 {
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
             break;
         }
+        file_hasher.update(&buffer[..read]);
+        size += read as u64;
+
+        // Percorre o bloco fechando um chunk a cada corte
+        let mut start = 0;
+        while start < read {
+            let data = &buffer[start..read];
+            let cut = chunker.find_cut(data, chunk_len);
+            let end = cut.unwrap_or(data.len());
+            chunk_hasher.update(&data[..end]);
+            chunk_len += end;
+            start += end;
+
+            if cut.is_some() {
+                chunk_hashes.push(format!("{:x}", std::mem::take(&mut chunk_hasher).finalize()));
+                chunk_lengths.push(chunk_len as u64);
+                chunk_len = 0;
+            }
+        }
+    }
 
-        let chunk_name = format!("{}.chunk{}", file_name, index);
-        let mut chunk_file = File::create(&chunk_name).expect("Erro ao criar chunk");
-        chunk_file.write_all(&buffer[..size]).expect("Erro ao escrever chunk");
+    if chunk_len > 0 {
+        chunk_hashes.push(format!("{:x}", chunk_hasher.finalize()));
+        chunk_lengths.push(chunk_len as u64);
+    }
 
-        let checksum = format!("{:x}", Sha256::digest(&buffer[..size]));
-        chunks.push((index, chunk_name.clone(), checksum));
-        index += 1;
+    if chunk_hashes.is_empty() {
+        return Err(P2pError::Protocol(format!("o arquivo '{}' está vazio", file_name)));
     }
 
-    println!("✅ Arquivo '{}' dividido em {} chunk(s).", file_name, index);
-    chunks
+    let manifest = FileManifest::new(
+        file_name,
+        size,
+        chunk_size,
+        options.mode,
+        chunk_lengths,
+        chunk_hashes,
+        format!("{:x}", file_hasher.finalize()),
+    );
+
+    println!(
+        "✅ Arquivo '{}' dividido em {} chunk(s) de {} KB ({}). ID: {}",
+        file_name,
+        manifest.chunk_count(),
+        chunk_size / 1024,
+        if options.mode == ChunkingMode::Cdc { "média, por conteúdo" } else { "fixos" },
+        manifest.id
+    );
+    Ok(manifest)
 }
 
 /// **Calcula o checksum do arquivo inteiro**
-pub fn compute_file_checksum(file_name: &str) -> String {
-    let mut file = match File::open(file_name) {
-        Ok(f) => f,
-        Err(_) => {
-            println!("⚠️ Arquivo '{}' não encontrado para calcular o checksum!", file_name);
-            return String::new();
-        }
-    };
-    
-    let mut hasher = Sha256::new();
-    let mut buffer = vec![0; CHUNK_SIZE];
-
-    while let Ok(size) = file.read(&mut buffer) {
-        if size == 0 {
-            break;
-        }
-        hasher.update(&buffer[..size]);
-    }
-
-    format!("{:x}", hasher.finalize())
+pub async fn compute_file_checksum(path: impl AsRef<Path>) -> P2pResult<String> {
+    let path = path.as_ref().to_path_buf();
+    blocking(move || checksum_blocking(&path)).await
 }
 
-/// **Reconstitui o arquivo original a partir dos chunks**
-pub fn assemble_file(original_file_name: &str) {
-    let output_file_name = format!("{}.assembled", original_file_name);
-    let mut output_file = File::create(&output_file_name)
-        .expect("❌ Erro ao criar arquivo final");
+fn checksum_blocking(path: &Path) -> P2pResult<String> {
+    let mut file = File::open(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("'{}': {}", path.display(), e)))?;
 
-    let mut index = 0;
-    let mut chunks_found = false;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; BUFFER_SIZE];
 
     loop {
-        let chunk_name = format!("{}.chunk{}", original_file_name, index);
-        if let Ok(mut chunk_file) = File::open(&chunk_name) {
-            let mut buffer = Vec::new();
-            chunk_file.read_to_end(&mut buffer).expect("❌ Erro ao ler chunk");
-            output_file.write_all(&buffer).expect("❌ Erro ao escrever no arquivo final");
-
-            println!("📦 Adicionando '{}' ao arquivo final", chunk_name);
-            chunks_found = true;
-        } else {
+        let size = file.read(&mut buffer)?;
+        if size == 0 {
             break;
         }
-        index += 1;
+        hasher.update(&buffer[..size]);
     }
 
-    if chunks_found {
-        println!("✅ Arquivo '{}' reconstituído com sucesso!", output_file_name);
-
-        let assembled_checksum = compute_file_checksum(&output_file_name);
-        println!("🔍 Checksum do arquivo reconstruído: {}", assembled_checksum);
-
-        if std::path::Path::new(original_file_name).exists() {
-            // 🔍 Se o arquivo original existir, compara os checksums
-            let original_checksum = compute_file_checksum(original_file_name);
-            println!("🔍 Checksum esperado: {}", original_checksum);
-        }
+    Ok(format!("{:x}", hasher.finalize()))
+}
 
-        // 🚀 Renomeia para o nome correto, com fallback caso ocorra erro
-        match fs::rename(&output_file_name, original_file_name) {
-            Ok(_) => println!("✅ O arquivo foi validado e renomeado corretamente para '{}'", original_file_name),
-            Err(e) => {
-                println!("❌ Erro ao renomear '{}': {}. Tentando copiar o arquivo...", output_file_name, e);
-                if let Err(copy_err) = fs::copy(&output_file_name, original_file_name) {
-                    println!("❌ Falha ao copiar arquivo reconstruído: {}", copy_err);
-                } else {
-                    println!("✅ Arquivo '{}' copiado com sucesso!", original_file_name);
-                    let _ = fs::remove_file(&output_file_name);
-                }
-            }
-        }
-    } else {
-        println!("⚠️ Nenhum chunk encontrado para reconstrução!");
-    }
-}
\ No newline at end of file
+/// **Calcula o checksum de um bloco já em memória** (um chunk recebido pela rede)
+pub async fn compute_checksum(bytes: impl AsRef<[u8]> + Send + 'static) -> String {
+    task::spawn_blocking(move || format!("{:x}", Sha256::digest(bytes.as_ref())))
+        .await
+        .unwrap_or_default()
+}
@@ -0,0 +1,309 @@
+use serde::{Serialize, Deserialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashMap;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::Mutex;
+use tokio::net::UdpSocket;
+use tokio::time::{self, Duration, Instant};
+
+use crate::download_state::DownloadManifest;
+use crate::manifest::FileManifest;
+use crate::peer::{address_matches, SharedState};
+use crate::piece_store;
+
+/// Grupo multicast padrão dos beacons (endereço de escopo local da organização)
+pub const DEFAULT_LAN_GROUP: &str = "239.255.70.80:7680";
+
+/// Intervalo entre os beacons de um peer
+pub const BEACON_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Espera pelas respostas ao primeiro beacon, antes de usar a tabela ao iniciar
+pub const SETTLE_TIME: Duration = Duration::from_secs(1);
+
+/// Intervalo mínimo entre beacons extras (enviados ao ver um peer novo)
+const MIN_BEACON_GAP: Duration = Duration::from_secs(1);
+
+/// Um peer sem beacons há mais que isso sai da tabela
+const LAN_PEER_TTL: Duration = Duration::from_secs(20);
+
+/// Tamanho máximo de um beacon serializado: o maior payload de um datagrama UDP sobre IPv4
+const MAX_BEACON_BYTES: usize = 65_507;
+
+/// **Arquivo anunciado num beacon**
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LanFile {
+    pub id: String,        // ID do conteúdo
+    pub file_name: String, // Nome do arquivo
+    pub size: u64,         // Tamanho em bytes
+}
+
+/// **Beacon enviado periodicamente ao grupo multicast**
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Beacon {
+    name: String,        // Nome do peer
+    address: String,     // Endereço anunciado do peer (host:porta)
+    files: Vec<LanFile>, // Arquivos completos e downloads em andamento
+}
+
+/// **Peer encontrado na rede local**, como mostrado pela API
+#[derive(Debug, Clone, Serialize)]
+pub struct LanPeer {
+    pub name: String,
+    pub address: String,
+    pub files: Vec<LanFile>,
+    pub last_seen: u64, // Segundos desde o último beacon
+}
+
+/// **Descoberta de peers na rede local por beacons UDP multicast**
+///
+/// Cada peer anuncia ao grupo, a cada `BEACON_INTERVAL`, seu nome, endereço e arquivos, e
+/// guarda os beacons dos outros numa tabela local. A tabela é usada junto com o tracker para
+/// listar peers e encontrar fontes, e permite usar a rede local sem nenhum tracker.
+pub struct LanDiscovery {
+    group: SocketAddrV4,
+    interface: Ipv4Addr,
+    peers: Mutex<HashMap<String, (Beacon, Instant)>>,
+}
+
+impl LanDiscovery {
+    /// Beacons no grupo `group`, pela interface do endereço anunciado `host` (todas se não for IPv4)
+    pub fn new(group: SocketAddrV4, host: &str) -> Self {
+        LanDiscovery {
+            group,
+            interface: host.parse().unwrap_or(Ipv4Addr::UNSPECIFIED),
+            peers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Peers com beacons recentes, ordenados pelo nome
+    pub fn peers(&self) -> Vec<LanPeer> {
+        let mut peers = self.peers.lock().unwrap();
+        peers.retain(|_, (_, seen)| seen.elapsed() <= LAN_PEER_TTL);
+
+        let mut list: Vec<LanPeer> = peers.values()
+            .map(|(beacon, seen)| LanPeer {
+                name: beacon.name.clone(),
+                address: beacon.address.clone(),
+                files: beacon.files.clone(),
+                last_seen: seen.elapsed().as_secs(),
+            })
+            .collect();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        list
+    }
+
+    /// Endereços dos peers que anunciaram o arquivo `file_id`
+    pub fn peers_with(&self, file_id: &str) -> Vec<String> {
+        self.peers().into_iter()
+            .filter(|peer| peer.files.iter().any(|file| file.id == file_id))
+            .map(|peer| peer.address)
+            .collect()
+    }
+
+    /// IDs dos arquivos chamados `file_name`, com os endereços de quem os anunciou
+    pub fn find_by_name(&self, file_name: &str) -> HashMap<String, Vec<String>> {
+        let mut found: HashMap<String, Vec<String>> = HashMap::new();
+        for peer in self.peers() {
+            for file in peer.files.iter().filter(|file| file.file_name == file_name) {
+                found.entry(file.id.clone()).or_default().push(peer.address.clone());
+            }
+        }
+        found
+    }
+
+    /// Endereço de um peer pelo nome, se só um peer da rede local o usa
+    pub fn address_of(&self, name: &str) -> Option<String> {
+        let mut addresses = self.peers().into_iter().filter(|peer| peer.name == name).map(|peer| peer.address);
+        let address = addresses.next()?;
+        addresses.next().is_none().then_some(address)
+    }
+
+    /// Socket no grupo multicast; vários peers da mesma máquina podem abrir a mesma porta
+    fn join(&self) -> io::Result<UdpSocket> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, self.group.port())).into())?;
+        socket.join_multicast_v4(self.group.ip(), &self.interface)?;
+        socket.set_multicast_if_v4(&self.interface)?;
+        socket.set_multicast_loop_v4(true)?; // Peers na mesma máquina também se enxergam
+        UdpSocket::from_std(socket.into())
+    }
+
+    /// Guarda um beacon recebido, ignorando os do próprio peer; retorna se o peer é novo
+    ///
+    /// O beacon só vale se o host anunciado for o IP de origem do datagrama, para que ninguém
+    /// na rede local anuncie o endereço (e as fontes) de outro peer.
+    fn receive(&self, bytes: &[u8], source: SocketAddr, own_address: &str) -> bool {
+        let Ok(beacon) = serde_json::from_slice::<Beacon>(bytes) else {
+            return false;
+        };
+        if beacon.address == own_address || !address_matches(&beacon.address, source) {
+            return false;
+        }
+
+        let mut peers = self.peers.lock().unwrap();
+        let new = !peers.contains_key(&beacon.address);
+        if new {
+            println!("📶 Peer '{}' encontrado na rede local ({})", beacon.name, beacon.address);
+        }
+        peers.insert(beacon.address.clone(), (beacon, Instant::now()));
+        new
+    }
+
+    /// **Só escuta os beacons por `duration`**, sem se anunciar (usado pelo comando `list`)
+    pub async fn listen(&self, duration: Duration) -> io::Result<()> {
+        let socket = self.join()?;
+        let deadline = Instant::now() + duration;
+        let mut buffer = vec![0u8; 65_536];
+        while let Ok(Ok((len, source))) = time::timeout_at(deadline, socket.recv_from(&mut buffer)).await {
+            self.receive(&buffer[..len], source, "");
+        }
+        Ok(())
+    }
+}
+
+/// Arquivos que este peer pode servir: os completos e os downloads com peças
+fn local_files(state: &SharedState) -> Vec<LanFile> {
+    let pending = DownloadManifest::pending(&state.storage);
+    let mut files: Vec<LanFile> = piece_store::local_bitfields(&state.storage).into_keys()
+        .filter_map(|id| {
+            FileManifest::load(&state.storage, &id)
+                .or_else(|| pending.iter().find(|download| download.file.id == id).map(|download| download.file.clone()))
+        })
+        .map(|file| LanFile { id: file.id, file_name: file.file_name, size: file.size })
+        .collect();
+    files.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+    files
+}
+
+/// **Serializa o beacon com tantos arquivos quantos couberem em `MAX_BEACON_BYTES`**
+///
+/// Os arquivos que não cabem ficam de fora (em ordem de nome); eles continuam sendo
+/// encontrados pelo tracker e pela DHT. Retorna `None` se nem o beacon sem arquivos couber.
+fn encode_beacon(mut beacon: Beacon) -> Option<Vec<u8>> {
+    let files = std::mem::take(&mut beacon.files);
+    let mut size = serde_json::to_vec(&beacon).ok()?.len();
+    let mut fitting = 0;
+    for (index, file) in files.iter().enumerate() {
+        // Cada arquivo ocupa o próprio JSON mais a vírgula que o separa do anterior
+        size += serde_json::to_vec(file).ok()?.len() + usize::from(index > 0);
+        if size > MAX_BEACON_BYTES {
+            break;
+        }
+        fitting += 1;
+    }
+    beacon.files = files;
+    beacon.files.truncate(fitting);
+
+    serde_json::to_vec(&beacon).ok().filter(|bytes| bytes.len() <= MAX_BEACON_BYTES)
+}
+
+/// Envia o beacon do peer ao grupo
+async fn send_beacon(state: &SharedState, socket: &UdpSocket, group: SocketAddrV4) {
+    let storage_state = state.clone();
+    let files = tokio::task::spawn_blocking(move || local_files(&storage_state)).await.unwrap_or_default();
+    let beacon = Beacon { name: state.name.clone(), address: state.address.clone(), files };
+    let Some(bytes) = encode_beacon(beacon) else {
+        println!("⚠️ O beacon de '{}' não cabe num datagrama UDP", state.name);
+        return;
+    };
+    if let Err(e) = socket.send_to(&bytes, group).await {
+        println!("⚠️ Erro ao enviar beacon para a rede local: {}", e);
+    }
+}
+
+/// **Envia os beacons do peer e recebe os dos outros** enquanto o peer estiver rodando
+///
+/// Ao ver um peer novo, o beacon é enviado na hora, para que quem acabou de entrar na rede
+/// conheça os demais sem esperar um intervalo inteiro.
+pub async fn run(state: SharedState) {
+    let Some(lan) = &state.lan else {
+        return;
+    };
+    let socket = match lan.join() {
+        Ok(socket) => socket,
+        Err(e) => {
+            println!("❌ Erro ao entrar no grupo multicast {}: {}", lan.group, e);
+            return;
+        }
+    };
+    println!("📶 Descoberta na rede local ativa (grupo {})", lan.group);
+
+    let mut interval = time::interval(BEACON_INTERVAL);
+    let mut last_sent = Instant::now();
+    let mut buffer = vec![0u8; 65_536];
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                send_beacon(&state, &socket, lan.group).await;
+                last_sent = Instant::now();
+            }
+            received = socket.recv_from(&mut buffer) => {
+                let Ok((len, source)) = received else {
+                    continue;
+                };
+                if lan.receive(&buffer[..len], source, &state.address) && last_sent.elapsed() >= MIN_BEACON_GAP {
+                    send_beacon(&state, &socket, lan.group).await;
+                    last_sent = Instant::now();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn beacon(name: &str, address: &str) -> Vec<u8> {
+        serde_json::to_vec(&Beacon { name: name.to_string(), address: address.to_string(), files: vec![] }).unwrap()
+    }
+
+    fn discovery() -> LanDiscovery {
+        LanDiscovery::new(DEFAULT_LAN_GROUP.parse().unwrap(), "127.0.0.1")
+    }
+
+    #[test]
+    fn beacons_must_come_from_the_announced_host() {
+        let lan = discovery();
+        let source: SocketAddr = "10.0.0.7:7680".parse().unwrap();
+
+        assert!(!lan.receive(&beacon("bob", "10.0.0.8:8000"), source, ""));
+        assert!(lan.receive(&beacon("alice", "10.0.0.7:8000"), source, ""));
+        assert!(!lan.receive(&beacon("alice", "10.0.0.7:8000"), source, ""));
+        assert!(!lan.receive(&beacon("eu", "10.0.0.7:9000"), source, "10.0.0.7:9000"));
+
+        assert_eq!(lan.address_of("alice").as_deref(), Some("10.0.0.7:8000"));
+        assert_eq!(lan.address_of("bob"), None);
+    }
+
+    #[test]
+    fn beacons_fit_in_one_datagram() {
+        let files: Vec<LanFile> = (0..400)
+            .map(|index| LanFile { id: format!("{:064x}", index), file_name: format!("{:0>255}", index), size: u64::MAX })
+            .collect();
+        let beacon = Beacon { name: "alice".to_string(), address: "10.0.0.7:8000".to_string(), files };
+
+        let bytes = encode_beacon(beacon.clone()).unwrap();
+        assert!(bytes.len() <= MAX_BEACON_BYTES);
+        let decoded: Beacon = serde_json::from_slice(&bytes).unwrap();
+        assert!(decoded.files.len() > 100 && decoded.files.len() < 400);
+        assert_eq!(decoded.files.last().unwrap().id, beacon.files[decoded.files.len() - 1].id);
+
+        // Mais um arquivo já passaria do limite
+        let one_more = Beacon { files: beacon.files[..decoded.files.len() + 1].to_vec(), ..beacon };
+        assert!(serde_json::to_vec(&one_more).unwrap().len() > MAX_BEACON_BYTES);
+    }
+
+    #[test]
+    fn repeated_names_do_not_resolve() {
+        let lan = discovery();
+        lan.receive(&beacon("alice", "10.0.0.7:8000"), "10.0.0.7:7680".parse().unwrap(), "");
+        lan.receive(&beacon("alice", "10.0.0.9:8000"), "10.0.0.9:7680".parse().unwrap(), "");
+
+        assert_eq!(lan.peers().len(), 2);
+        assert_eq!(lan.address_of("alice"), None);
+    }
+}
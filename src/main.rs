@@ -2,30 +2,88 @@ mod tracker;
 mod peer;
 mod file_utils;
 mod chat;
+mod api;
+mod events;
+mod storage;
+mod download_state;
+mod scheduler;
+mod chunking;
+mod piece_store;
+mod throttle;
+mod choke;
+mod pex;
+mod dht;
+mod lan;
+mod manifest;
+mod error;
+mod config;
+mod tracker_store;
+mod tracker_list;
+mod federation;
 
+use config::LayeredConfig;
 use std::env;
 use tokio::runtime::Runtime;
 
+/// Resumo dos comandos não interativos do peer
+const PEER_USAGE: &str = "Comandos do peer:
+  peer                                      modo interativo
+  peer --name NOME share CAMINHO...         compartilha arquivos e continua servindo
+  peer --name NOME get ARQUIVO|ID [--connections N] [--seed]
+  peer --name NOME import MANIFESTO [--connections N] [--seed]
+  peer [--name NOME] export ARQUIVO|ID [DESTINO]
+  peer --name NOME resume [--seed]          retoma downloads interrompidos
+  peer list                                 lista peers e arquivos
+  peer --name NOME chat DESTINO MENSAGEM    DESTINO = nome do peer ou host:porta";
+
 fn main() {
     let args: Vec<String> = env::args().collect();
 
     if args.len() < 2 {
-        eprintln!("❌ Uso: cargo run -- (tracker | peer)");
+        eprintln!("❌ Uso: cargo run -- (tracker [--port PORTA] [--store journal|memory] [--journal-path ARQUIVO] [--federation URLS] | peer [--tracker-url URL] [--bind-host HOST] [--advertise-host HOST] [--port PORTA] [--config ARQUIVO])");
         return;
     }
 
     let mode = args[1].as_str();
 
     let rt = Runtime::new().expect("❌ Falha ao iniciar o runtime do Tokio");
+    let cli = config::CliArgs::parse(&args[2..]);
 
     match mode {
         "tracker" => {
+            let tracker_config = match config::TrackerConfig::load(&cli) {
+                Ok(tracker_config) => tracker_config,
+                Err(e) => {
+                    eprintln!("❌ Configuração inválida: {}", e);
+                    return;
+                }
+            };
+
             println!("🚀 Iniciando Tracker...");
-            rt.block_on(tracker::start_tracker()); // ❌ REMOVIDO `if let Err(e) =`
+            if let Err(e) = rt.block_on(tracker::start_tracker(tracker_config)) {
+                eprintln!("❌ {}", e);
+            }
         }
         "peer" => {
-            println!("📡 Iniciando Peer...");
-            rt.block_on(peer::start_peer()); // ❌ REMOVIDO `if let Err(e) =`
+            let peer_config = match config::PeerConfig::load(&cli) {
+                Ok(peer_config) => peer_config,
+                Err(e) => {
+                    eprintln!("❌ Configuração inválida: {}", e);
+                    return;
+                }
+            };
+
+            match peer::PeerCommand::from_args(&cli) {
+                Ok(Some(command)) => rt.block_on(peer::run_command(peer_config, command)),
+                Ok(None) => {
+                    println!("📡 Iniciando Peer...");
+                    rt.block_on(peer::start_peer(peer_config)); // ❌ REMOVIDO `if let Err(e) =`
+                }
+                Err(e) => {
+                    eprintln!("❌ {}", e);
+                    eprintln!("{}", PEER_USAGE);
+                }
+            }
         }
         _ => {
             eprintln!("❌ Modo inválido! Use 'tracker' ou 'peer'.");
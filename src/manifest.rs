@@ -0,0 +1,377 @@
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::chunking::ChunkingMode;
+use crate::storage::Storage;
+
+/// Extensão sugerida para manifestos exportados
+pub const MANIFEST_EXTENSION: &str = "p2pmeta";
+
+/// Cabeçalho HTTP com a prova de inclusão enviada junto de cada chunk em `/get_chunk`
+pub const PROOF_HEADER: &str = "x-merkle-proof";
+
+/// **Manifesto de um arquivo compartilhado** (equivalente a um .torrent)
+///
+/// O `id` é o hash da raiz da árvore de hashes dos chunks junto com todos os metadados
+/// (nome, tamanho, divisão e checksum), então dois arquivos homônimos com conteúdos
+/// diferentes têm IDs diferentes, e um manifesto com o ID de outro arquivo mas com
+/// metadados trocados não passa em `verify`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileManifest {
+    pub id: String,                // Hash da raiz e dos metadados; identifica o arquivo na rede
+    pub root: String,              // Raiz da árvore de hashes dos chunks, usada nas provas de inclusão
+    pub file_name: String,         // Nome sugerido para o arquivo reconstruído
+    pub size: u64,                 // Tamanho total em bytes
+    pub chunk_size: usize,         // Tamanho de cada chunk (o último pode ser menor); em `cdc`, o tamanho médio
+    #[serde(default)]
+    pub chunking: ChunkingMode,    // Como os chunks foram delimitados
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunk_lengths: Vec<u64>,   // Tamanho de cada chunk, só no modo `cdc`
+    pub chunk_hashes: Vec<String>, // SHA-256 de cada chunk, em ordem
+    pub checksum: String,          // SHA-256 do arquivo inteiro
+}
+
+impl FileManifest {
+    pub fn new(
+        file_name: &str,
+        size: u64,
+        chunk_size: usize,
+        chunking: ChunkingMode,
+        chunk_lengths: Vec<u64>,
+        chunk_hashes: Vec<String>,
+        checksum: String,
+    ) -> Self {
+        let mut manifest = FileManifest {
+            id: String::new(),
+            root: root_hash(&chunk_hashes),
+            file_name: file_name.to_string(),
+            size,
+            chunk_size,
+            chunking,
+            // Em `fixed` os tamanhos são deduzidos de `chunk_size`
+            chunk_lengths: if chunking == ChunkingMode::Cdc { chunk_lengths } else { vec![] },
+            chunk_hashes,
+            checksum,
+        };
+        manifest.id = manifest.content_id();
+        manifest
+    }
+
+    /// **ID derivado do manifesto**: SHA-256 da raiz e dos metadados, serializados em JSON
+    fn content_id(&self) -> String {
+        let fields = (&self.root, &self.file_name, self.size, self.chunk_size, self.chunking, &self.chunk_lengths, &self.checksum);
+        let encoded = serde_json::to_vec(&fields).unwrap_or_default();
+        format!("{:x}", Sha256::digest(encoded))
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunk_hashes.len()
+    }
+
+    /// Nome do chunk `index` deste arquivo
+    pub fn chunk_name(&self, index: usize) -> String {
+        chunk_name(&self.id, index)
+    }
+
+    /// Nomes e hashes de todos os chunks, em ordem
+    pub fn chunks(&self) -> impl Iterator<Item = (String, &String)> {
+        self.chunk_hashes.iter().enumerate().map(|(index, hash)| (self.chunk_name(index), hash))
+    }
+
+    /// **Posição do chunk `index` dentro do arquivo**: deslocamento e tamanho em bytes
+    pub fn chunk_range(&self, index: usize) -> Option<(u64, u64)> {
+        if index >= self.chunk_count() {
+            return None;
+        }
+        match self.chunking {
+            ChunkingMode::Fixed => {
+                let offset = index as u64 * self.chunk_size as u64;
+                Some((offset, (self.size - offset).min(self.chunk_size as u64)))
+            }
+            ChunkingMode::Cdc => {
+                let offset = self.chunk_lengths[..index].iter().sum();
+                Some((offset, self.chunk_lengths[index]))
+            }
+        }
+    }
+
+    /// Posição do chunk a partir do nome, se ele pertencer a este arquivo
+    pub fn chunk_index(&self, chunk_name: &str) -> Option<usize> {
+        parse_chunk_name(chunk_name)
+            .filter(|(file_id, index)| *file_id == self.id && *index < self.chunk_count())
+            .map(|(_, index)| index)
+    }
+
+    /// Prova de inclusão do chunk `index` na árvore cuja raiz é `root`
+    pub fn proof(&self, index: usize) -> Option<Vec<String>> {
+        merkle_proof(&self.chunk_hashes, index)
+    }
+
+    /// **Confere se o manifesto é coerente**: raiz igual à dos hashes, ID igual ao da raiz com os
+    /// metadados e quantidade de chunks compatível com o tamanho
+    pub fn verify(&self) -> Result<(), String> {
+        if self.root != root_hash(&self.chunk_hashes) {
+            return Err("a raiz não corresponde aos hashes dos chunks".to_string());
+        }
+        if self.id != self.content_id() {
+            return Err("o ID não corresponde à raiz e aos metadados do manifesto".to_string());
+        }
+        if self.chunk_size == 0 || self.chunk_hashes.is_empty() {
+            return Err("manifesto sem chunks".to_string());
+        }
+        match self.chunking {
+            ChunkingMode::Fixed => {
+                let expected_chunks = self.size.div_ceil(self.chunk_size as u64);
+                if expected_chunks != self.chunk_count() as u64 {
+                    return Err(format!("{} chunk(s) para {} bytes, esperado {}", self.chunk_count(), self.size, expected_chunks));
+                }
+            }
+            ChunkingMode::Cdc => {
+                if self.chunk_lengths.len() != self.chunk_count() || self.chunk_lengths.contains(&0) {
+                    return Err("tamanhos dos chunks não correspondem aos hashes".to_string());
+                }
+                if self.chunk_lengths.iter().sum::<u64>() != self.size {
+                    return Err(format!("a soma dos chunks difere do tamanho de {} bytes", self.size));
+                }
+            }
+        }
+        if !crate::storage::is_safe_name(&self.file_name) {
+            return Err(format!("nome de arquivo inválido: '{}'", self.file_name));
+        }
+        Ok(())
+    }
+
+    /// Grava o manifesto em JSON legível em qualquer caminho
+    pub fn export(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Lê e valida um manifesto exportado
+    pub fn import(path: impl AsRef<Path>) -> Result<FileManifest, String> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Erro ao ler '{}': {}", path.display(), e))?;
+        let manifest: FileManifest = serde_json::from_str(&content)
+            .map_err(|e| format!("Manifesto '{}' inválido: {}", path.display(), e))?;
+        manifest.verify().map_err(|e| format!("Manifesto '{}' inválido: {}", path.display(), e))?;
+        Ok(manifest)
+    }
+
+    /// Guarda o manifesto em `manifests/<id>.json` no diretório do peer
+    pub fn save(&self, storage: &Storage) -> io::Result<()> {
+        self.export(storage.manifest_path(&self.id))
+    }
+
+    /// Carrega o manifesto local de um arquivo pelo ID
+    pub fn load(storage: &Storage, id: &str) -> Option<FileManifest> {
+        let content = fs::read_to_string(storage.manifest_path(id)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Manifestos de todos os arquivos conhecidos por este peer
+    pub fn list(storage: &Storage) -> Vec<FileManifest> {
+        storage.list_manifests()
+            .iter()
+            .filter_map(|name| name.strip_suffix(".json"))
+            .filter_map(|id| FileManifest::load(storage, id))
+            .collect()
+    }
+
+    /// Procura um manifesto local pelo ID ou pelo nome do arquivo
+    pub fn find_local(storage: &Storage, id_or_name: &str) -> Option<FileManifest> {
+        FileManifest::load(storage, id_or_name)
+            .or_else(|| FileManifest::list(storage).into_iter().find(|manifest| manifest.file_name == id_or_name))
+    }
+}
+
+/// Nome do chunk `index` do arquivo `file_id`
+pub fn chunk_name(file_id: &str, index: usize) -> String {
+    format!("{}{}", chunk_prefix(file_id), index)
+}
+
+/// Prefixo comum aos nomes de todos os chunks do arquivo `file_id`
+pub fn chunk_prefix(file_id: &str) -> String {
+    format!("{}.chunk", file_id)
+}
+
+/// Separa o nome `<id>.chunkN` no ID do arquivo e na posição do chunk
+pub fn parse_chunk_name(chunk_name: &str) -> Option<(&str, usize)> {
+    let (file_id, index) = chunk_name.rsplit_once(".chunk")?;
+    Some((file_id, index.parse().ok()?))
+}
+
+/// Indica se o texto tem o formato de um ID de arquivo (SHA-256 em hexadecimal)
+pub fn is_file_id(value: &str) -> bool {
+    value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// **Raiz da árvore de hashes dos chunks**
+///
+/// Cada nível combina os hashes dois a dois (`SHA-256(esquerda || direita)`, sobre o texto
+/// hexadecimal); um hash sem par sobe para o nível seguinte sem alteração.
+pub fn root_hash(chunk_hashes: &[String]) -> String {
+    if chunk_hashes.is_empty() {
+        return format!("{:x}", Sha256::digest(b""));
+    }
+
+    let mut level: Vec<String> = chunk_hashes.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level.remove(0)
+}
+
+/// **Prova de inclusão de um chunk**: os irmãos do caminho da folha até a raiz, de baixo para cima
+///
+/// Níveis em que o nó não tem irmão (hash promovido sem alteração) não entram na prova.
+pub fn merkle_proof(chunk_hashes: &[String], index: usize) -> Option<Vec<String>> {
+    if index >= chunk_hashes.len() {
+        return None;
+    }
+
+    let mut proof = vec![];
+    let mut level: Vec<String> = chunk_hashes.to_vec();
+    let mut position = index;
+    while level.len() > 1 {
+        if let Some(sibling) = level.get(position ^ 1) {
+            proof.push(sibling.clone());
+        }
+        level = next_level(&level);
+        position /= 2;
+    }
+    Some(proof)
+}
+
+/// **Confere a prova de inclusão de um chunk contra a raiz da árvore do arquivo**
+///
+/// O lado de cada irmão é deduzido da posição do chunk e da quantidade de chunks, e não
+/// da prova, para que um peer não consiga encaixar um hash em outra posição da árvore.
+pub fn verify_proof(root: &str, chunk_hash: &str, index: usize, chunk_count: usize, proof: &[String]) -> bool {
+    if index >= chunk_count {
+        return false;
+    }
+
+    let mut siblings = proof.iter();
+    let mut hash = chunk_hash.to_string();
+    let mut position = index;
+    let mut width = chunk_count;
+    while width > 1 {
+        if position % 2 == 1 {
+            let Some(left) = siblings.next() else { return false };
+            hash = hash_pair(left, &hash);
+        } else if position + 1 < width {
+            let Some(right) = siblings.next() else { return false };
+            hash = hash_pair(&hash, right);
+        }
+        position /= 2;
+        width = width.div_ceil(2);
+    }
+
+    siblings.next().is_none() && hash == root
+}
+
+/// Codifica a prova para o cabeçalho `PROOF_HEADER` (hashes separados por vírgula)
+pub fn encode_proof(proof: &[String]) -> String {
+    proof.join(",")
+}
+
+/// Lê a prova recebida no cabeçalho `PROOF_HEADER`
+pub fn decode_proof(value: &str) -> Vec<String> {
+    value.split(',')
+        .map(|hash| hash.trim().to_string())
+        .filter(|hash| !hash.is_empty())
+        .collect()
+}
+
+/// Combina um nível da árvore dois a dois, promovendo o último hash se não tiver par
+fn next_level(level: &[String]) -> Vec<String> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => hash_pair(left, right),
+            [single] => single.clone(),
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Hash de dois nós vizinhos da árvore
+fn hash_pair(left: &str, right: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(left.as_bytes());
+    hasher.update(right.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hashes de `count` chunks distintos
+    fn leaves(count: usize) -> Vec<String> {
+        (0..count).map(|index| format!("{:x}", Sha256::digest(format!("chunk {}", index)))).collect()
+    }
+
+    fn manifest(file_name: &str) -> FileManifest {
+        FileManifest::new(file_name, 10, 4, ChunkingMode::Fixed, vec![], leaves(3), "checksum".to_string())
+    }
+
+    #[test]
+    fn proofs_verify_for_every_chunk() {
+        for count in 1..=9 {
+            let hashes = leaves(count);
+            let root = root_hash(&hashes);
+            for (index, hash) in hashes.iter().enumerate() {
+                let proof = merkle_proof(&hashes, index).unwrap();
+                assert!(verify_proof(&root, hash, index, count, &proof), "{} chunk(s), índice {}", count, index);
+                assert!(verify_proof(&root, hash, index, count, &decode_proof(&encode_proof(&proof))));
+            }
+            assert_eq!(merkle_proof(&hashes, count), None);
+        }
+    }
+
+    #[test]
+    fn proof_is_rejected_at_another_index() {
+        for count in 2..=9 {
+            let hashes = leaves(count);
+            let root = root_hash(&hashes);
+            for (index, hash) in hashes.iter().enumerate() {
+                let proof = merkle_proof(&hashes, index).unwrap();
+                for other in (0..count).filter(|other| *other != index) {
+                    assert!(!verify_proof(&root, hash, other, count, &proof), "{} chunk(s), {} no lugar de {}", count, index, other);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn proof_is_rejected_with_wrong_hash_or_siblings() {
+        let hashes = leaves(5);
+        let root = root_hash(&hashes);
+        let proof = merkle_proof(&hashes, 2).unwrap();
+
+        assert!(!verify_proof(&root, &hashes[3], 2, 5, &proof));
+        assert!(!verify_proof(&root, &hashes[2], 2, 5, &proof[1..]));
+        let mut longer = proof.clone();
+        longer.push(hashes[0].clone());
+        assert!(!verify_proof(&root, &hashes[2], 2, 5, &longer));
+    }
+
+    #[test]
+    fn id_binds_the_metadata() {
+        let original = manifest("a.bin");
+        assert_eq!(original.verify(), Ok(()));
+        assert_ne!(original.id, manifest("b.bin").id);
+
+        let mut renamed = original.clone();
+        renamed.file_name = "b.bin".to_string();
+        assert!(renamed.verify().is_err());
+
+        let mut rehashed = original.clone();
+        rehashed.chunk_hashes[1] = leaves(4)[3].clone();
+        assert!(rehashed.verify().is_err());
+    }
+}
@@ -1,23 +1,44 @@
-use axum::{extract::Query, http::StatusCode, Router}; // Framework web para criar APIs HTTP
+use axum::{body::Body, extract::{ConnectInfo, Json, Query, State}, http::{header::RETRY_AFTER, HeaderMap, HeaderValue, StatusCode}, response::{IntoResponse, Response}, Router}; // Framework web para criar APIs HTTP
 use reqwest::Client; // Cliente HTTP para comunicação com o tracker
-use serde::{Serialize, Deserialize}; // Serialização e deserialização de JSON
+use serde::{de::DeserializeOwned, Serialize, Deserialize}; // Serialização e deserialização de JSON
 use std::collections::{HashMap, HashSet}; // Estruturas de dados para mapear peers e arquivos
-use std::{error::Error, sync::Arc, io, fs}; // Tratamento de erros e manipulação de arquivos
+use std::{sync::{Arc, Mutex}, io}; // Sincronização e entrada/saída
 use tokio::net::TcpListener; // Listener TCP para aceitar conexões de outros peers
 use rand::Rng; // Gerador de números aleatórios
-use std::fs::File; // Manipulação de arquivos
-use std::io::{Read, Write}; // Leitura e escrita de arquivos
+use rand::seq::IteratorRandom;
 use tokio::time::{self, Duration}; // Utilitários para tempo e delays assíncronos
 use axum::routing::{get, post}; // Rotas HTTP para interações P2P
-use rand::prelude::SliceRandom; // Escolha aleatória de peers ao baixar arquivos
 use rfd::FileDialog;
 use std::path::Path;
+use tokio::task::JoinSet;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+use futures::StreamExt;
 use tokio::time::timeout;
 use tokio::time::sleep;
 use std::time::Instant;
+use std::net::SocketAddr;
+use tower_http::cors::{CorsLayer, Any};
 
+use crate::api;
 use crate::chat;
-use crate::file_utils::{split_file, assemble_file, compute_file_checksum};
+use crate::choke::{self, UnchokeStrategy, UploadSlots};
+use crate::config::{CliArgs, PeerConfig};
+use crate::dht::{self, Dht, DhtMode};
+use crate::lan::{self, LanDiscovery};
+use crate::error::{P2pError, P2pResult};
+use crate::events::{EventBus, PeerEvent};
+use crate::download_state::DownloadManifest;
+use crate::piece_store::Bitfield;
+use crate::chunking::{ChunkingMode, ChunkingOptions};
+use crate::scheduler::new_scheduler;
+use crate::throttle::{self, Throttle};
+use crate::file_utils::{split_file, compute_checksum};
+use crate::piece_store;
+use crate::pex::{self, PexTable};
+use crate::manifest::{self, FileManifest};
+use crate::storage::{self, Storage};
+use crate::tracker_list::TrackerList;
 
 
 
@@ -33,18 +54,140 @@ struct RegisterRequest {
 #[derive(Debug, Serialize, Deserialize, Clone, Hash, Eq, PartialEq)]
 struct ChunkRegister {
     peer: String,
+    file_id: String,
     file_name: String,
     chunk_name: String,
     checksum: String,
     peer_address: String,
 }
 
+// Manifesto de um arquivo publicado no tracker
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct FileRegister {
+    peer: String,
+    manifest: FileManifest,
+}
+
 // Estado compartilhado do peer
-#[allow(dead_code)]
-struct PeerState {
-    name: String,          // Nome do peer
-    tracker_url: String,   // URL do tracker
-    address: String,       // Endereço do peer
+pub struct PeerState {
+    pub name: String,                      // Nome do peer
+    pub trackers: TrackerList,             // Trackers em níveis, em ordem de prioridade
+    pub address: String,                   // Endereço do peer
+    pub downloads: Mutex<HashSet<String>>, // IDs dos arquivos sendo baixados no momento
+    pub events: EventBus,                  // Eventos publicados para a interface
+    pub storage: Storage,                  // Diretórios de arquivos, chunks e downloads do peer
+    pub scheduler: String,                 // Estratégia de escolha de chunks (`rarest` ou `random`)
+    pub max_connections: usize,            // Limite configurado de conexões paralelas por download
+    pub connection_policy: String,         // Conexões usadas por padrão (`fixed` ou `chunks`)
+    pub chunking: ChunkingOptions,         // Tamanho e modo de divisão dos arquivos compartilhados
+    pub throttle: Arc<Throttle>,           // Limites de banda de upload e download
+    pub upload_slots: UploadSlots,         // Vagas de upload (choke/unchoke)
+    pub pex: PexTable,                     // Peers com quem houve troca de chunks recentemente
+    pub dht: Option<Dht>,                  // Nó da DHT (`None` com `--dht off`)
+    pub lan: Option<LanDiscovery>,         // Peers da rede local (`None` sem `--lan`)
+    pub api_origins: Vec<String>,          // Origens do frontend aceitas pela API de controle
+    pub share_dirs: Vec<String>,           // Diretórios que `/api/share` pode compartilhar
+}
+
+impl PeerState {
+    /// O tracker só fica de fora com `--dht only`
+    pub fn tracker_enabled(&self) -> bool {
+        self.dht.as_ref().is_none_or(|dht| dht.mode != DhtMode::Only)
+    }
+
+    /// Há como achar peers sem o tracker (DHT ou rede local)
+    pub fn peer_discovery(&self) -> bool {
+        self.dht.is_some() || self.lan.is_some()
+    }
+
+    /// Publica `TrackerUnreachable` quando uma requisição ao tracker não consegue ser enviada
+    fn tracker_unreachable(&self, tracker_url: &str, error: &reqwest::Error) {
+        println!("📴 Tracker {} inacessível: {}", tracker_url, error);
+        self.events.publish(PeerEvent::TrackerUnreachable {
+            tracker_url: tracker_url.to_string(),
+            error: error.to_string(),
+        });
+    }
+
+    /// GET em `path` de um tracker; quem responde passa para a frente do seu nível
+    async fn tracker_get(&self, client: &Client, tracker_url: &str, path: &str) -> P2pResult<reqwest::Response> {
+        let res = client.get(format!("{}{}", tracker_url, path))
+            .send()
+            .await
+            .inspect_err(|e| self.tracker_unreachable(tracker_url, e))?;
+        self.trackers.promote(tracker_url);
+        Ok(res)
+    }
+
+    /// **GET no primeiro tracker que responder**, em ordem de prioridade
+    async fn query_trackers(&self, client: &Client, path: &str) -> P2pResult<reqwest::Response> {
+        let mut last_error = None;
+        for tracker_url in self.trackers.ordered() {
+            match self.tracker_get(client, &tracker_url, path).await {
+                Ok(res) => return Ok(res),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(no_trackers))
+    }
+
+    /// **Primeira resposta aceita por `accept`**, perguntando aos trackers em ordem de prioridade
+    ///
+    /// Passa para o próximo tracker quando um está inacessível, responde com erro ou não tem o
+    /// que foi pedido. `Ok(None)` se algum respondeu, mas nenhum tinha.
+    async fn first_answer<T: DeserializeOwned>(&self, path: &str, accept: impl Fn(&T) -> bool) -> P2pResult<Option<T>> {
+        let client = Client::new();
+        let mut last_error = None;
+        let mut answered = false;
+        for tracker_url in self.trackers.ordered() {
+            let res = match self.tracker_get(&client, &tracker_url, path).await {
+                Ok(res) if res.status().is_success() => res,
+                Ok(_) => {
+                    answered = true;
+                    continue;
+                }
+                Err(e) => {
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+            match res.json::<T>().await {
+                Ok(answer) if accept(&answer) => return Ok(Some(answer)),
+                Ok(_) => answered = true,
+                Err(e) => last_error = Some(e.into()),
+            }
+        }
+        match last_error {
+            Some(e) if !answered => Err(e),
+            _ => Ok(None),
+        }
+    }
+
+    /// **POST em `path` de todos os trackers ao mesmo tempo** (anúncios)
+    async fn announce<T: Serialize>(&self, path: &str, payload: &T) -> Vec<(String, P2pResult<reqwest::Response>)> {
+        let client = Client::new();
+        let requests = self.trackers.ordered().into_iter().map(|tracker_url| {
+            let client = &client;
+            async move {
+                let result = client.post(format!("{}{}", tracker_url, path))
+                    .json(payload)
+                    .send()
+                    .await
+                    .inspect_err(|e| self.tracker_unreachable(&tracker_url, e))
+                    .map_err(P2pError::from);
+                if result.is_ok() {
+                    self.trackers.promote(&tracker_url);
+                }
+                (tracker_url, result)
+            }
+        });
+        futures::future::join_all(requests).await
+    }
+}
+
+/// Erro de quando não há nenhum tracker configurado
+fn no_trackers() -> P2pError {
+    P2pError::Protocol("Nenhum tracker configurado".to_string())
 }
 
 // Informações sobre um peer
@@ -52,57 +195,142 @@ struct PeerState {
 struct PeerInfo {
     name: String,          // Nome do peer
     address: String,       // Endereço do peer
-    files: Vec<String>,    // Lista de arquivos compartilhados
+    files: Vec<SharedFile>, // Lista de arquivos compartilhados
 }
 
-#[allow(dead_code)]
-type SharedState = Arc<PeerState>;
+// Arquivo compartilhado por um peer
+#[derive(Debug, Serialize, Deserialize)]
+struct SharedFile {
+    id: String,   // ID do conteúdo
+    name: String, // Nome do arquivo
+}
 
-/// Registra um novo peer no tracker
-async fn register_peer(name: &str, address: &str) -> bool {
-    let client = Client::new();
+pub type SharedState = Arc<PeerState>;
+
+/// Chunk baixado e verificado, com o tempo gasto para medir a vazão do peer
+struct ChunkDownload {
+    chunk_name: String,
+    peer_address: String,
+    bytes: usize,
+    elapsed: Duration,
+}
+
+/// Chunk que não pôde ser baixado de um peer
+struct ChunkFailure {
+    chunk_name: String,
+    peer_address: String,
+    choked: Option<Duration>, // O peer estava sem vagas de upload: tentar nele de novo depois desse tempo
+}
+
+impl ChunkFailure {
+    fn failed(chunk_name: String, peer_address: String) -> Self {
+        ChunkFailure { chunk_name, peer_address, choked: None }
+    }
+}
+
+/// Resultado do download de um chunk
+type ChunkResult = Result<ChunkDownload, ChunkFailure>;
+
+/// Marca um arquivo como "em download" enquanto estiver vivo
+///
+/// A marca é feita por `reserve_download`; quem a fez passa a ser dono dela e a libera ao terminar.
+struct ActiveDownload<'a> {
+    state: &'a PeerState,
+    file_id: String,
+}
+
+/// **Reserva o download de `file_id`**; retorna `false` se ele já está em andamento
+///
+/// A verificação e a marca acontecem juntas, para que dois pedidos simultâneos do mesmo
+/// arquivo não gravem no mesmo `downloads/<id>.part`. Depois de reservar, o download deve
+/// seguir por `download_reserved`, que libera a reserva ao terminar.
+pub fn reserve_download(state: &PeerState, file_id: &str) -> bool {
+    state.downloads.lock().unwrap().insert(file_id.to_string())
+}
+
+impl Drop for ActiveDownload<'_> {
+    fn drop(&mut self) {
+        self.state.downloads.lock().unwrap().remove(&self.file_id);
+    }
+}
+
+/// Registra um novo peer em todos os trackers
+///
+/// Basta um tracker aceitar. Retorna `Err` se nenhum estiver acessível e `Ok(false)` se os
+/// que responderam recusarem o registro.
+async fn register_peer(state: &PeerState) -> P2pResult<bool> {
     let request = RegisterRequest {
-        name: name.to_string(),
-        address: address.to_string(),
+        name: state.name.clone(),
+        address: state.address.clone(),
     };
 
     // Envia requisição POST para registro
-    let res = client.post("http://127.0.0.1:9500/register")
-        .json(&request)
-        .send()
-        .await;
-
-    match res {
-        Ok(response) if response.status().is_success() => {
-            println!("✅ Peer '{}' registrado com sucesso!", name);
-            true
-        }
-        _ => {
-            println!("❌ Nome de usuário já está em uso. Escolha outro.");
-            false
+    let mut registered = 0;
+    let mut rejected = false;
+    let mut last_error = None;
+    let results = state.announce("/register", &request).await;
+    let total = results.len();
+    for (tracker_url, result) in results {
+        match result {
+            Ok(res) if res.status().is_success() => registered += 1,
+            Ok(_) => {
+                println!("⚠️ Tracker {} recusou o registro de '{}'.", tracker_url, state.name);
+                rejected = true;
+            }
+            Err(e) => last_error = Some(e),
         }
     }
-}
 
-/// **Envia heartbeat para o Tracker a cada 60 segundos**
-async fn send_heartbeat(peer_name: String) {
-    let client = Client::new();
-    let url = "http://127.0.0.1:9500/heartbeat".to_string();
+    if registered > 0 {
+        println!("✅ Peer '{}' registrado com sucesso em {} de {} tracker(s)!", state.name, registered, total);
+        Ok(true)
+    } else if rejected {
+        println!("❌ Nome de usuário já está em uso. Escolha outro.");
+        Ok(false)
+    } else {
+        Err(last_error.unwrap_or_else(no_trackers))
+    }
+}
 
+/// **Envia heartbeat para todos os trackers a cada 60 segundos**
+///
+/// Um tracker que não conhece o peer (estava fora do ar no registro ou perdeu o estado)
+/// recebe de novo o registro e os arquivos completos do peer.
+async fn send_heartbeat(state: SharedState) {
     loop {
         sleep(Duration::from_secs(60)).await; // Espera 60 segundos antes de enviar o próximo heartbeat
 
-        let res = client.post(&url)
-            .json(&peer_name)
-            .send()
-            .await;
-
-        match res {
-            Ok(response) if response.status().is_success() => {
-                println!("💓 Heartbeat enviado para o Tracker!");
+        for (tracker_url, result) in state.announce("/heartbeat", &state.name).await {
+            match result {
+                Ok(response) if response.status().is_success() => {
+                    println!("💓 Heartbeat enviado para o Tracker {}!", tracker_url);
+                }
+                Ok(response) if response.status() == reqwest::StatusCode::NOT_FOUND => {
+                    rejoin_tracker(&state, &tracker_url).await;
+                }
+                Ok(_) => println!("❌ Falha ao enviar heartbeat para o Tracker {}!", tracker_url),
+                Err(_) => {} // Já avisado por `announce`
             }
-            _ => {
-                println!("❌ Falha ao enviar heartbeat para o Tracker!");
+        }
+    }
+}
+
+/// Registra de novo o peer e seus arquivos completos num tracker que não o conhece
+async fn rejoin_tracker(state: &PeerState, tracker_url: &str) {
+    let client = Client::new();
+    let request = RegisterRequest {
+        name: state.name.clone(),
+        address: state.address.clone(),
+    };
+    match client.post(format!("{}/register", tracker_url)).json(&request).send().await {
+        Ok(res) if res.status().is_success() => println!("🔁 Peer '{}' registrado de novo no Tracker {}", state.name, tracker_url),
+        _ => return,
+    }
+
+    for manifest in FileManifest::list(&state.storage) {
+        if piece_store::has_complete_file(&state.storage, &manifest).await {
+            if let Err(e) = publish_to_tracker(state, tracker_url, &manifest).await {
+                println!("❌ Erro ao registrar '{}' no Tracker {}: {}", manifest.file_name, tracker_url, e);
             }
         }
     }
@@ -117,107 +345,165 @@ fn select_file() -> Option<String> {
 }
 
 
-/// **Copia um arquivo para o diretório do peer**
-fn copy_file_to_peer_directory(file_path: &str) -> Option<String> {
-    let path = std::path::Path::new(file_path);
+/// **Guarda o arquivo compartilhado em `files/<id>/<nome>`**
+///
+/// O destino vem do ID do conteúdo, então uma versão editada de um arquivo já compartilhado
+/// ganha outro lugar em vez de ser ignorada. A cópia só é pulada se o destino já tiver
+/// exatamente esse conteúdo; arquivos soltos em `files/` são movidos em vez de copiados.
+///
+/// Para não ocupar o disco duas vezes, o destino é um *hard link* para o original; só em
+/// outro sistema de arquivos (ou onde links não são permitidos) o conteúdo é copiado. Com o
+/// link, editar o original no lugar altera também o que é servido: os chunks deixam de
+/// conferir e são recusados por quem baixa, então o arquivo editado deve ser compartilhado de novo.
+async fn copy_file_to_peer_directory(storage: &Storage, source: &Path, manifest: &FileManifest) -> P2pResult<()> {
+    let destination = storage.content_path(&manifest.id, &manifest.file_name);
+    if piece_store::has_complete_file(storage, manifest).await {
+        println!("⚠️ '{}' já está em '{}'. Pulando cópia.", manifest.file_name, destination.display());
+        return Ok(());
+    }
 
-    if let Some(file_name) = path.file_name() {
-        let destination = format!("./{}", file_name.to_string_lossy());
+    tokio::fs::create_dir_all(storage.content_dir(&manifest.id)).await?;
+    // Um destino estragado é descartado antes: o link ou a cópia nunca escrevem sobre ele
+    match tokio::fs::remove_file(&destination).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    if source.parent() == Some(storage.files_dir().as_path()) {
+        tokio::fs::rename(source, &destination).await?;
+        println!("📂 Arquivo movido para '{}'", destination.display());
+    } else if tokio::fs::hard_link(source, &destination).await.is_ok() {
+        println!("🔗 Arquivo ligado em '{}' (sem cópia)", destination.display());
+    } else {
+        tokio::fs::copy(source, &destination).await?;
+        println!("📂 Arquivo copiado para '{}'", destination.display());
+    }
 
-        // 🚀 Verifica se o arquivo original já existe
-        if std::path::Path::new(&destination).exists() {
-            println!("⚠️ Arquivo '{}' já existe. Pulando cópia.", destination);
-            return Some(destination);
-        }
+    // O arquivo pode ter mudado entre a divisão e a cópia
+    if !piece_store::has_complete_file(storage, manifest).await {
+        let _ = tokio::fs::remove_dir_all(storage.content_dir(&manifest.id)).await;
+        return Err(P2pError::ChecksumMismatch {
+            file: manifest.file_name.clone(),
+            detail: "o arquivo foi alterado durante o compartilhamento".to_string(),
+        });
+    }
+    Ok(())
+}
 
-        // 🚀 Se o arquivo não existir, evita erro de cópia
-        if !std::path::Path::new(file_path).exists() {
-            println!("❌ Arquivo '{}' não encontrado para cópia!", file_path);
-            return None;
-        }
 
-        if let Err(e) = std::fs::copy(file_path, &destination) {
-            println!("❌ Erro ao copiar arquivo: {}", e);
-            return None;
-        }
+/// Registra chunks de arquivos no Tracker
+/// **Registra um arquivo a partir de qualquer diretório**
+pub async fn register_chunks(state: &PeerState, file_path: &str) -> P2pResult<()> {
+    let source = Path::new(file_path);
+    println!("📂 Processando '{}'", source.display());
 
-        println!("📂 Arquivo copiado para '{}'", destination);
-        return Some(destination);
-    }
+    // Divide o arquivo original em chunks e gera o manifesto (ID do conteúdo, hashes e checksum)
+    let manifest = split_file(source, state.chunking).await?;
 
-    None
+    // Liga (ou copia) o arquivo no diretório do peer, no lugar do seu conteúdo
+    copy_file_to_peer_directory(&state.storage, source, &manifest).await?;
+    manifest.save(&state.storage)?;
+
+    publish_file(state, &manifest).await
 }
 
+/// **Publica nos trackers o manifesto e os chunks locais de um arquivo**
+///
+/// Usado também depois de um download, com o manifesto original: dividir o arquivo de
+/// novo com as opções deste peer poderia gerar outros chunks e outro ID. Basta um tracker
+/// aceitar; os demais voltam a receber o arquivo quando o peer se registrar de novo neles.
+async fn publish_file(state: &PeerState, manifest: &FileManifest) -> P2pResult<()> {
+    let file_name = manifest.file_name.clone();
+    if let Some(dht) = &state.dht {
+        dht.announce(&manifest.id).await;
+    }
+    if !state.tracker_enabled() {
+        println!("🆔 '{}' compartilhado com o ID {}", file_name, manifest.id);
+        return Ok(());
+    }
 
-/// Registra chunks de arquivos no Tracker
-/// **Registra um arquivo a partir de qualquer diretório**
-async fn register_chunks(peer_name: &str, peer_address: &str, file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
-    // Copia o arquivo para o diretório do peer antes de processá-lo
-    let local_file_path = match copy_file_to_peer_directory(file_path) {
-        Some(path) => path,
-        None => {
-            println!("❌ Falha ao copiar arquivo '{}'", file_path);
-            return Ok(());
+    let trackers = state.trackers.ordered();
+    let results = futures::future::join_all(trackers.iter().map(|tracker_url| publish_to_tracker(state, tracker_url, manifest))).await;
+    let mut published = 0;
+    let mut first_error = None;
+    for (tracker_url, result) in trackers.iter().zip(results) {
+        match result {
+            Ok(()) => published += 1,
+            Err(e) if trackers.len() > 1 => {
+                println!("⚠️ '{}' não foi registrado no Tracker {}: {}", file_name, tracker_url, e);
+                first_error.get_or_insert(e);
+            }
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
         }
-    };
-
-    // Obtém apenas o nome do arquivo, sem o caminho absoluto
-    let file_name = Path::new(&local_file_path)
-        .file_name()
-        .unwrap()
-        .to_string_lossy()
-        .to_string();
+    }
 
-    println!("📂 Processando '{}'", file_name);
+    match first_error {
+        Some(P2pError::Tracker(_)) if published == 0 && state.peer_discovery() => {
+            println!("🆔 '{}' compartilhado com o ID {} (só pela DHT e pela rede local)", file_name, manifest.id);
+            Ok(())
+        }
+        Some(e) if published == 0 => Err(e),
+        _ => {
+            println!("🆔 '{}' compartilhado com o ID {}", file_name, manifest.id);
+            Ok(())
+        }
+    }
+}
 
+/// Publica o manifesto e os chunks de um arquivo em um tracker
+async fn publish_to_tracker(state: &PeerState, tracker_url: &str, manifest: &FileManifest) -> P2pResult<()> {
+    let file_name = manifest.file_name.clone();
     let client = Client::new();
     
     // Verifica se o arquivo já está registrado no Tracker
-    let url = format!("http://127.0.0.1:9500/list");
-    let res = client.get(&url).send().await?;
+    let res = state.tracker_get(&client, tracker_url, "/list").await?;
 
     if res.status().is_success() {
         let list: Vec<PeerInfo> = res.json().await?;
         for peer_info in list {
-            if peer_info.name == peer_name && peer_info.files.contains(&file_name) {
-                println!("⚠️ O arquivo '{}' já está registrado no Tracker. Ignorando...", file_name);
+            if peer_info.name == state.name && peer_info.files.iter().any(|file| file.id == manifest.id) {
+                println!("⚠️ O arquivo '{}' já está registrado no Tracker {}. Ignorando...", file_name, tracker_url);
                 return Ok(());
             }
         }
     }
 
-    // Divide o arquivo em chunks
-    let chunks = split_file(&file_name);
-    if chunks.is_empty() {
-        println!("❌ Nenhum chunk foi criado para '{}'. Verifique se o arquivo existe.", file_name);
-        return Ok(());
+    // Publica o manifesto do arquivo
+    let file_data = FileRegister {
+        peer: state.name.clone(),
+        manifest: manifest.clone(),
+    };
+
+    let res = client.post(format!("{}/register_file", tracker_url))
+        .json(&file_data)
+        .send()
+        .await?;
+
+    if !res.status().is_success() {
+        return Err(P2pError::rejected(res).await);
     }
 
     // Registra cada chunk no Tracker
-    for (_, chunk_name, expected_checksum) in &chunks {
-        let computed_checksum = compute_file_checksum(chunk_name);
-        if computed_checksum != *expected_checksum {
-            println!("❌ Erro: Checksum inválido para '{}'. Chunk corrompido.", chunk_name);
-            continue;
-        }
-
+    for (chunk_name, expected_checksum) in manifest.chunks() {
         let chunk_data = ChunkRegister {
-            peer: peer_name.to_string(),
-            peer_address: peer_address.to_string(),
+            peer: state.name.clone(),
+            peer_address: state.address.clone(),
+            file_id: manifest.id.clone(),
             file_name: file_name.clone(), // 🔹 Apenas o nome do arquivo, sem caminho absoluto
-            chunk_name: chunk_name.to_string(),
+            chunk_name: chunk_name.clone(),
             checksum: expected_checksum.to_string(),
         };
 
-        let res = client.post("http://127.0.0.1:9500/register_chunk")
+        let res = client.post(format!("{}/register_chunk", tracker_url))
             .json(&chunk_data)
             .send()
             .await?;
 
         if res.status().is_success() {
-            println!("✅ Chunk '{}' registrado no Tracker!", chunk_name);
+            println!("✅ Chunk '{}' registrado no Tracker {}!", chunk_name, tracker_url);
         } else {
-            println!("❌ Erro ao registrar chunk '{}'", chunk_name);
+            println!("❌ Erro ao registrar chunk '{}' no Tracker {}", chunk_name, tracker_url);
         }
     }
 
@@ -226,56 +512,316 @@ async fn register_chunks(peer_name: &str, peer_address: &str, file_path: &str) -
 
 
 
-/// Obtém a lista de chunks disponíveis no tracker
-async fn get_chunks(file_name: &str) -> Result<Vec<ChunkRegister>, Box<dyn Error>> {
+/// **Obtém as fontes dos chunks de um arquivo**
+///
+/// Junta às fontes do tracker (ou da DHT) as dos peers da rede local que anunciaram o arquivo
+/// nos seus beacons; sem tracker, as da rede local bastam.
+async fn get_chunks(state: &PeerState, file: &FileManifest) -> Result<Vec<ChunkRegister>, P2pError> {
+    let found = network_chunks(state, file).await;
+    let Some(lan) = &state.lan else {
+        return found;
+    };
+    let neighbors = lan.peers_with(&file.id);
+    if neighbors.is_empty() {
+        return found;
+    }
+
+    let mut chunks = pex_chunks(state, &neighbors, file).await;
+    println!("📶 Rede local: {} peer(s) com '{}'", neighbors.len(), file.file_name);
+    match found {
+        Ok(found) => chunks.extend(found),
+        Err(e) if chunks.is_empty() => return Err(e),
+        Err(_) => {}
+    }
+
+    // Um mesmo peer pode vir do tracker e da rede local
+    let mut seen: HashSet<(String, String)> = HashSet::new();
+    chunks.retain(|chunk| seen.insert((chunk.peer_address.clone(), chunk.chunk_name.clone())));
+    Ok(chunks)
+}
+
+/// **Fontes de um arquivo pelo tracker e pela DHT**
+///
+/// Pergunta ao tracker; com `--dht fallback`, recorre à DHT quando o tracker falha ou não
+/// conhece fontes, e com `--dht only` pergunta só à DHT.
+async fn network_chunks(state: &PeerState, file: &FileManifest) -> Result<Vec<ChunkRegister>, P2pError> {
+    let Some(dht) = &state.dht else {
+        return tracker_chunks(state, &file.id).await;
+    };
+    if dht.mode == DhtMode::Only {
+        return Ok(dht_chunks(state, dht, file).await);
+    }
+
+    match tracker_chunks(state, &file.id).await {
+        Ok(chunks) if !chunks.is_empty() => Ok(chunks),
+        result => {
+            println!("🌐 Procurando fontes de '{}' na DHT...", file.file_name);
+            let chunks = dht_chunks(state, dht, file).await;
+            if chunks.is_empty() {
+                result
+            } else {
+                Ok(chunks)
+            }
+        }
+    }
+}
+
+/// **Obtém a lista de chunks disponíveis nos trackers**
+///
+/// Pergunta a todos ao mesmo tempo e junta as respostas, sem repetir o mesmo chunk de um
+/// mesmo endereço (peers homônimos de trackers diferentes são fontes distintas). Só falha se
+/// nenhum tracker responder.
+async fn tracker_chunks(state: &PeerState, file_id: &str) -> Result<Vec<ChunkRegister>, P2pError> {
     let client = Client::new();
-    let url = format!("http://127.0.0.1:9500/get_file_chunks?file={}", file_name);
-    let res = client.get(&url).send().await?;
+    let path = format!("/get_file_chunks?id={}", file_id);
+    let trackers = state.trackers.ordered();
+    let replies = futures::future::join_all(trackers.iter().map(|tracker_url| state.tracker_get(&client, tracker_url, &path))).await;
+
+    let mut chunks: Vec<ChunkRegister> = vec![];
+    let mut seen: HashSet<(String, String)> = HashSet::new();
+    let mut last_error = None;
+    let mut answered = false;
+    for (tracker_url, reply) in trackers.iter().zip(replies) {
+        let res = match reply {
+            Ok(res) => res,
+            Err(e) => {
+                last_error = Some(e);
+                continue;
+            }
+        };
+        if !res.status().is_success() {
+            println!("❌ Erro ao buscar chunks do arquivo '{}' no Tracker {}.", file_id, tracker_url);
+            answered = true;
+            continue;
+        }
+        match res.json::<Vec<ChunkRegister>>().await {
+            Ok(found) => {
+                answered = true;
+                chunks.extend(found.into_iter().filter(|chunk| seen.insert((chunk.peer_address.clone(), chunk.chunk_name.clone()))));
+            }
+            Err(e) => last_error = Some(e.into()),
+        }
+    }
 
-    if res.status().is_success() {
-        let chunks: Vec<ChunkRegister> = res.json().await?;
-        Ok(chunks)
-    } else {
-        println!("❌ Erro ao buscar chunks do arquivo '{}'.", file_name);
-        Ok(vec![])
+    match last_error {
+        Some(e) if !answered => Err(e),
+        _ => Ok(chunks),
+    }
+}
+
+/// **Fontes de um arquivo pela DHT**
+///
+/// A DHT só diz quais peers têm o arquivo; os chunks de cada um vêm da lista PEX do peer.
+async fn dht_chunks(state: &PeerState, dht: &Dht, file: &FileManifest) -> Vec<ChunkRegister> {
+    let addresses = dht.find_peers(&file.id).await;
+    if addresses.is_empty() {
+        println!("🌐 Nenhum peer anunciou '{}' na DHT.", file.file_name);
+        return vec![];
+    }
+
+    println!("🌐 DHT: {} peer(s) com '{}'", addresses.len(), file.file_name);
+    pex_chunks(state, &addresses, file).await
+}
+
+/// **Chunks de um arquivo nos peers `addresses`**, pela lista PEX de cada um
+///
+/// A lista inclui as peças que o próprio peer tem e as dos peers com quem ele trocou chunks.
+async fn pex_chunks(state: &PeerState, addresses: &[String], file: &FileManifest) -> Vec<ChunkRegister> {
+    let client = Client::new();
+    let replies = futures::future::join_all(addresses.iter().map(|address| pex::fetch_peers(&client, address, &file.id))).await;
+    let mut chunks: HashSet<ChunkRegister> = HashSet::new();
+    for peer in replies.into_iter().flatten().flatten() {
+        if peer.address == state.address {
+            continue;
+        }
+        for pieces in peer.files.iter().filter(|pex_file| pex_file.id == file.id).map(|pex_file| &pex_file.pieces) {
+            for (index, (chunk_name, checksum)) in file.chunks().enumerate() {
+                if pieces.get(index) {
+                    chunks.insert(ChunkRegister {
+                        peer: peer.address.clone(),
+                        file_id: file.id.clone(),
+                        file_name: file.file_name.clone(),
+                        chunk_name,
+                        checksum: checksum.clone(),
+                        peer_address: peer.address.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    chunks.into_iter().collect()
+}
+
+/// **Manifesto de um arquivo sem o tracker**: pergunta aos peers da rede local e da DHT
+async fn peer_manifest(state: &PeerState, file_id: &str) -> Option<FileManifest> {
+    if let Some(lan) = &state.lan {
+        if let Some(file) = fetch_manifest(&lan.peers_with(file_id), file_id).await {
+            return Some(file);
+        }
+    }
+    match &state.dht {
+        Some(dht) => fetch_manifest(&dht.find_peers(file_id).await, file_id).await,
+        None => None,
     }
 }
 
-/// Conta quantos chunks este peer tem no diretório
-fn count_local_chunks() -> usize {
-    let mut chunk_count = 0;
+/// **Pede `/manifest` aos peers `addresses`** até um responder
+///
+/// O manifesto só é aceito se a árvore de hashes conferir com o ID pedido.
+async fn fetch_manifest(addresses: &[String], file_id: &str) -> Option<FileManifest> {
+    let client = Client::new();
+    for address in addresses {
+        let url = format!("http://{}/manifest?id={}", address, file_id);
+        let request = async {
+            client.get(&url).send().await?.error_for_status()?.json::<FileManifest>().await
+        };
+        match timeout(Duration::from_secs(5), request).await {
+            Ok(Ok(file)) if file.id == file_id && file.verify().is_ok() => return Some(file),
+            Ok(Ok(_)) => println!("⚠️ Manifesto inválido recebido de '{}'", address),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// **Envia o manifesto de um arquivo local (`GET /manifest?id=<arquivo>`)**
+///
+/// Usado por quem encontrou este peer pela DHT ou na rede local e ainda não conhece o arquivo.
+async fn send_manifest(
+    State(state): State<SharedState>,
+    Query(params): Query<std::collections::HashMap<String, String>>,
+) -> Result<Json<FileManifest>, (StatusCode, Json<String>)> {
+    let Some(file_id) = params.get("id").filter(|id| manifest::is_file_id(id)) else {
+        return Err((StatusCode::BAD_REQUEST, Json("Informe o ID do arquivo".to_string())));
+    };
+
+    FileManifest::load(&state.storage, file_id)
+        .or_else(|| DownloadManifest::load(&state.storage, file_id).map(|download| download.file))
+        .map(Json)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, Json(format!("Arquivo '{}' não encontrado", file_id))))
+}
+
+/// Busca nos trackers, em ordem de prioridade, o manifesto de um arquivo pelo ID
+async fn get_file_info(state: &PeerState, file_id: &str) -> Result<Option<FileManifest>, P2pError> {
+    let path = format!("/get_file_info?id={}", file_id);
+    Ok(state.first_answer::<Option<FileManifest>>(&path, Option::is_some).await?.flatten())
+}
+
+/// **Encontra o manifesto de um arquivo a partir do ID ou do nome**
+///
+/// Pelo nome, só resolve se houver um único conteúdo com esse nome na rede; caso
+/// contrário, pede que o arquivo seja escolhido pelo ID.
+pub async fn resolve_file(state: &PeerState, id_or_name: &str) -> Result<FileManifest, P2pError> {
+    if manifest::is_file_id(id_or_name) {
+        let found = if state.tracker_enabled() { get_file_info(state, id_or_name).await } else { Ok(None) };
+        if !state.peer_discovery() {
+            return found?.ok_or_else(|| P2pError::NotFound(format!("Arquivo com ID '{}' não encontrado no Tracker", id_or_name)));
+        }
+        if let Ok(Some(file)) = found {
+            return Ok(file);
+        }
+
+        // 🌐 O tracker não respondeu ou não conhece o arquivo: pergunta aos peers da rede local e da DHT
+        return match peer_manifest(state, id_or_name).await {
+            Some(file) => Ok(file),
+            None => {
+                found?;
+                Err(P2pError::NotFound(format!("Arquivo com ID '{}' não encontrado na rede", id_or_name)))
+            }
+        };
+    }
+
+    let found = if state.tracker_enabled() { find_files(state, id_or_name).await } else { Ok(vec![]) };
+    let mut tracker_error = None;
+    let mut candidates = match found {
+        Ok(files) => files,
+        Err(e) if state.lan.is_none() => return Err(e),
+        Err(e) => {
+            tracker_error = Some(e);
+            vec![]
+        }
+    };
 
-    if let Ok(entries) = fs::read_dir(".") {
-        for entry in entries.flatten() {
-            if let Some(file_name) = entry.file_name().to_str() {
-                if file_name.contains(".chunk") {
-                    chunk_count += 1;
+    // 📶 Arquivos com o mesmo nome anunciados na rede local
+    if let Some(lan) = &state.lan {
+        for (file_id, addresses) in lan.find_by_name(id_or_name) {
+            if candidates.iter().all(|file| file.id != file_id) {
+                if let Some(file) = fetch_manifest(&addresses, &file_id).await {
+                    candidates.push(file);
                 }
             }
         }
     }
 
-    chunk_count
+    match candidates.len() {
+        0 if !state.tracker_enabled() && state.lan.is_none() => {
+            Err(P2pError::Protocol(format!("Sem tracker, busque '{}' pelo ID ou importe o manifesto", id_or_name)))
+        }
+        0 => {
+            let place = if state.lan.is_some() { "na rede" } else { "no Tracker" };
+            Err(tracker_error.unwrap_or_else(|| P2pError::NotFound(format!("Arquivo '{}' não encontrado {}", id_or_name, place))))
+        }
+        1 => Ok(candidates.remove(0)),
+        _ => {
+            let ids: Vec<String> = candidates.iter().map(|file| format!("{} ({} bytes)", file.id, file.size)).collect();
+            Err(P2pError::Protocol(format!("Há {} arquivos chamados '{}'; escolha pelo ID: {}", candidates.len(), id_or_name, ids.join(", "))))
+        }
+    }
+}
+
+/// Busca nos trackers, em ordem de prioridade, os arquivos com um nome
+async fn find_files(state: &PeerState, file_name: &str) -> Result<Vec<FileManifest>, P2pError> {
+    let path = format!("/find_files?name={}", file_name);
+    Ok(state.first_answer::<Vec<FileManifest>>(&path, |found| !found.is_empty()).await?.unwrap_or_default())
+}
+
+/// Conta quantos chunks este peer pode servir (arquivos completos e peças de downloads)
+pub fn count_local_chunks(storage: &Storage) -> usize {
+    piece_store::available_chunks(storage).len()
 }
 
-/// Define o número máximo de conexões com base nos chunks disponíveis
-fn determine_max_connections() -> usize {
-    let chunk_count = count_local_chunks();
+/// Define o número de conexões usado quando o download não pede um
+///
+/// Com a política `fixed` (padrão) é o limite configurado em `max_connections` (4 por padrão).
+/// Com `chunks`, começa com 1 conexão e ganha mais uma a cada 5 chunks locais, até esse limite.
+pub fn determine_max_connections(state: &PeerState) -> usize {
+    match state.connection_policy.as_str() {
+        "chunks" => (count_local_chunks(&state.storage) / 5 + 1).min(state.max_connections),
+        _ => state.max_connections,
+    }
+}
 
-    match chunk_count {
-        0..=4 => 1,    // Apenas 1 conexão paralela
-        5..=9 => 2,    // Máximo de 2 conexões
-        10..=14 => 3,  // Máximo de 3 conexões
-        _ => 4,        // Máximo permitido (4)
+/// Conexões de um download: as pedidas, até `max_connections`, ou as da política configurada
+pub fn choose_connections(state: &PeerState, requested: Option<usize>) -> usize {
+    match requested {
+        Some(connections) => connections.clamp(1, state.max_connections),
+        None => determine_max_connections(state),
     }
 }
 
 
 /// Lista todos os peers e arquivos disponíveis na rede
-async fn list_peers() -> Result<(), Box<dyn Error>> {
+async fn list_peers(state: &PeerState) -> Result<(), P2pError> {
+    if let Some(lan) = &state.lan {
+        print_lan_peers(lan);
+        if !state.tracker_enabled() {
+            return Ok(());
+        }
+    } else if !state.tracker_enabled() {
+        println!("📋 Sem tracker: os peers só são encontrados pela DHT, a partir do ID de um arquivo.");
+        return Ok(());
+    }
+
     let client = Client::new();
-    let url = "http://127.0.0.1:9500/list".to_string();
-    let res = client.get(&url).send().await?;
+    let res = match state.query_trackers(&client, "/list").await {
+        Ok(res) => res,
+        Err(e) if state.lan.is_some() => {
+            println!("⚠️ Tracker inacessível ({}); mostrando só a rede local.", e);
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
     
     if res.status().is_success() {
         let list: Vec<PeerInfo> = res.json().await?;
@@ -286,7 +832,7 @@ async fn list_peers() -> Result<(), Box<dyn Error>> {
                 println!("  📄 Sem arquivos compartilhados");
             } else {
                 for file in peer.files {
-                    println!("  📄 {}", file);
+                    println!("  📄 {} ({})", file.name, file.id);
                 }
             }
         }
@@ -296,108 +842,332 @@ async fn list_peers() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-/// Baixa os chunks diretamente dos peers e os salva localmente
-/// Baixa os chunks diretamente dos peers e os salva localmente.
-/// Agora, ele continua tentando até baixar todos os chunks necessários.
+/// Mostra os peers encontrados na rede local e os arquivos anunciados por eles
+fn print_lan_peers(lan: &LanDiscovery) {
+    let peers = lan.peers();
+    if peers.is_empty() {
+        println!("📶 Nenhum peer encontrado na rede local.");
+        return;
+    }
+
+    println!("📶 Peers na rede local:");
+    for peer in peers {
+        println!("🔹 Peer: {} ({}, visto há {}s)", peer.name, peer.address, peer.last_seen);
+        if peer.files.is_empty() {
+            println!("  📄 Sem arquivos compartilhados");
+        }
+        for file in peer.files {
+            println!("  📄 {} ({})", file.file_name, file.id);
+        }
+    }
+}
+
+/// Backoff de um peer que falhou ao enviar chunks
+struct PeerBackoff {
+    failures: u32,     // Falhas consecutivas
+    retry_at: Instant, // Momento a partir do qual o peer pode ser usado de novo
+}
+
+/// Falhas consecutivas depois das quais um peer deixa de ser usado neste download
+const MAX_PEER_FAILURES: u32 = 5;
+
+/// Espera máxima entre tentativas num mesmo peer
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Tempo máximo para receber um chunk
+///
+/// Vale para a resposta e para cada bloco do corpo, e não para o chunk inteiro: com limite
+/// de banda, um chunk grande pode levar bem mais que isso para chegar.
+const CHUNK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Motivo da falha ao receber um chunk
+enum FetchFailure {
+    Http(reqwest::Error),
+    Timeout,
+    Choked(Duration),
+}
+
+impl From<reqwest::Error> for FetchFailure {
+    fn from(e: reqwest::Error) -> Self {
+        FetchFailure::Http(e)
+    }
+}
+
+impl From<time::error::Elapsed> for FetchFailure {
+    fn from(_: time::error::Elapsed) -> Self {
+        FetchFailure::Timeout
+    }
+}
+
+/// Tempo de espera pedido por um peer sem vagas de upload, se a resposta for um `choke`
+fn choke_wait(response: &reqwest::Response) -> Option<Duration> {
+    if response.status() != reqwest::StatusCode::SERVICE_UNAVAILABLE || !response.headers().contains_key(choke::CHOKED_HEADER) {
+        return None;
+    }
+    let seconds = response.headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(choke::ROUND_INTERVAL.as_secs());
+    Some(Duration::from_secs(seconds.max(1)))
+}
+
+/// Baixa um chunk de um peer, confere sua prova de inclusão e o grava no arquivo parcial
+///
+/// O chunk é verificado contra a raiz da árvore de hashes (o ID do arquivo), e não
+/// contra o checksum anunciado no tracker.
+async fn fetch_chunk(
+    client: Client,
+    events: EventBus,
+    storage: Storage,
+    throttle: Arc<Throttle>,
+    file: Arc<FileManifest>,
+    chunk_name: String,
+    peer_address: String,
+) -> ChunkResult {
+    let chunk_url = format!("http://{}/get_chunk?name={}", peer_address, chunk_name);
+    //println!("⬇️ Baixando chunk '{}' de '{}'", chunk_name, peer_address);
+    let started = Instant::now();
+
+    let request = async {
+        let response = timeout(CHUNK_TIMEOUT, client.get(&chunk_url).send()).await??;
+        if let Some(wait) = choke_wait(&response) {
+            return Err(FetchFailure::Choked(wait));
+        }
+        let mut response = response.error_for_status()?;
+        let proof = response.headers()
+            .get(manifest::PROOF_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(manifest::decode_proof);
+
+        // 🚦 Recebe o corpo em blocos, respeitando os limites de download global e do peer
+        let mut bytes = Vec::new();
+        while let Some(block) = timeout(CHUNK_TIMEOUT, response.chunk()).await?? {
+            throttle.download(&peer_address, block.len()).await;
+            bytes.extend_from_slice(&block);
+        }
+        Ok::<_, FetchFailure>((proof, bytes))
+    };
+    let (proof, bytes) = match request.await {
+        Ok(response) => response,
+        Err(FetchFailure::Http(e)) => {
+            println!("❌ Falha ao baixar '{}' de '{}': {}", chunk_name, peer_address, e);
+            return Err(ChunkFailure::failed(chunk_name, peer_address));
+        }
+        Err(FetchFailure::Choked(wait)) => {
+            return Err(ChunkFailure { chunk_name, peer_address, choked: Some(wait) });
+        }
+        Err(FetchFailure::Timeout) => {
+            println!("⏳ Timeout ao baixar '{}' de '{}'.", chunk_name, peer_address);
+            return Err(ChunkFailure::failed(chunk_name, peer_address));
+        }
+    };
+    let size = bytes.len(); // Obtém o tamanho do chunk baixado
+
+    let Some(proof) = proof else {
+        println!("❌ '{}' não enviou a prova de inclusão de '{}'.", peer_address, chunk_name);
+        return Err(ChunkFailure::failed(chunk_name, peer_address));
+    };
+
+    // 🌳 O hash do chunk, com a prova, precisa levar exatamente à raiz do arquivo
+    let downloaded_checksum = compute_checksum(bytes.clone()).await;
+    let index = file.chunk_index(&chunk_name)
+        .filter(|index| manifest::verify_proof(&file.root, &downloaded_checksum, *index, file.chunk_count(), &proof));
+    let Some(index) = index else {
+        println!("❌ Prova de inclusão inválida para '{}'. Chunk corrompido.", chunk_name);
+        events.publish(PeerEvent::ChecksumMismatch {
+            file: file.file_name.clone(),
+            chunk: chunk_name.clone(),
+            peer: peer_address.clone(),
+        });
+        return Err(ChunkFailure::failed(chunk_name, peer_address));
+    };
+
+    // ✅ Só depois de verificado o chunk é gravado na sua posição do arquivo parcial
+    if let Err(e) = piece_store::write_piece(&storage, &file, index, bytes).await {
+        println!("❌ Erro ao gravar '{}': {}", chunk_name, e);
+        return Err(ChunkFailure::failed(chunk_name, peer_address));
+    }
+
+    //println!("✅ Chunk '{}' baixado com sucesso! ({} KB)", chunk_name, size / 1024);
+    events.publish(PeerEvent::ChunkDownloaded {
+        file: file.file_name.clone(),
+        chunk: chunk_name.clone(),
+        peer: peer_address.clone(),
+        bytes: size,
+    });
+    Ok(ChunkDownload { chunk_name, peer_address, bytes: size, elapsed: started.elapsed() })
+}
+
 /// Baixa os chunks diretamente dos peers e os salva localmente.
 /// Agora evita baixar de si mesmo e distribui melhor os downloads.
+///
+/// Funciona como um pool de `max_connections` conexões: assim que um chunk termina,
+/// a conexão livre já pede o próximo ao escalonador. Um peer que falha entra em
+/// backoff exponencial (só ele) e, após `MAX_PEER_FAILURES` falhas seguidas, é
+/// descartado neste download.
+///
+/// Cada chunk é gravado na sua posição de `downloads/<id>.part` só depois de
+/// verificado, registrando o progresso no estado persistido do download.
+/// Retorna erro se algum chunk ficar sem nenhuma fonte disponível; o estado salvo
+/// permite retomar o download depois com `resume`.
 async fn download_chunks(
+    state: &PeerState,
+    manifest: &mut DownloadManifest,
     chunks: Vec<ChunkRegister>,
-    file_name: &str,
-    self_address: &str,
     max_connections: usize,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let client = Client::new();
-    let max_connections = max_connections.clamp(1, 4);
-    let mut chunk_map: HashMap<String, Vec<ChunkRegister>> = HashMap::new();
+) -> Result<(), P2pError> {
+    let file = Arc::new(manifest.file.clone());
+    let file_name = file.file_name.clone();
+    let max_connections = max_connections.max(1);
+    let mut chunk_map: HashMap<String, Vec<String>> = HashMap::new();
     let mut total_downloaded_bytes = 0; // Armazena o total de bytes baixados
-
-    // 🔹 Agrupa os chunks pelo nome
-    for chunk in chunks {
-        if chunk.peer_address != self_address {
-            chunk_map.entry(chunk.chunk_name.clone()).or_default().push(chunk);
+    let mut scheduler = new_scheduler(&state.scheduler);
+
+    // 🔹 Agrupa os peers de cada chunk pelo nome, somando as fontes salvas no estado do download
+    let saved_sources = manifest.chunks.iter()
+        .flat_map(|(chunk_name, progress)| progress.sources.iter().map(move |peer| (chunk_name.clone(), peer.clone())));
+    let announced = chunks.into_iter().map(|chunk| (chunk.chunk_name, chunk.peer_address));
+    for (chunk_name, peer_address) in announced.chain(saved_sources) {
+        if peer_address != state.address {
+            let peers = chunk_map.entry(chunk_name).or_default();
+            if !peers.contains(&peer_address) {
+                peers.push(peer_address);
+            }
         }
     }
 
-    let mut missing_chunks: HashSet<String> = chunk_map.keys().cloned().collect();
-    
+    // Os chunks pendentes vêm do estado do download, mesmo que o tracker não conheça mais nenhuma fonte
+    let mut missing_chunks: HashSet<String> = manifest.missing().into_iter().collect();
+    let mut in_flight: HashSet<String> = HashSet::new();     // Chunks sendo baixados agora
+    let mut busy: HashMap<String, usize> = HashMap::new();   // Conexões abertas com cada peer
+    let mut backoff: HashMap<String, PeerBackoff> = HashMap::new();
+    let mut dropped: HashSet<String> = HashSet::new();       // Peers descartados por falhas seguidas
+    let mut tasks: JoinSet<ChunkResult> = JoinSet::new();
+    let mut next_pex = Instant::now() + PEX_INTERVAL;
+
+    // 🪪 Os pedidos levam o endereço deste peer, usado nas vagas de upload de quem responde
+    let mut default_headers = reqwest::header::HeaderMap::new();
+    if let Ok(value) = reqwest::header::HeaderValue::from_str(&state.address) {
+        default_headers.insert(choke::PEER_HEADER, value);
+    }
+    let client = Client::builder().default_headers(default_headers).build()?;
+
     // 🚀 Inicia o cronômetro antes do download
     let start_time = Instant::now();
 
     while !missing_chunks.is_empty() {
-        let mut tasks: Vec<tokio::task::JoinHandle<Result<(String, usize), (String, String)>>> = vec![];
-        let chunks_to_process: Vec<_> = missing_chunks.iter().cloned().collect();
-
-        for chunk_name in chunks_to_process.iter().take(max_connections) {
-            if let Some(chunk_peers) = chunk_map.get_mut(chunk_name) {
-                let mut rng = rand::thread_rng();
-                chunk_peers.shuffle(&mut rng);
-
-                if let Some(selected_peer) = chunk_peers.pop() {
-                    let chunk_name_clone = chunk_name.clone();
-                    let peer_address = selected_peer.peer_address.clone();
-                    let checksum = selected_peer.checksum.clone();
-                    let client_clone = client.clone();
-
-                    tasks.push(tokio::spawn(async move {
-                        let chunk_url = format!("http://{}/get_chunk?name={}", peer_address, chunk_name_clone);
-                        //println!("⬇️ Baixando chunk '{}' de '{}'", chunk_name_clone, peer_address);
-
-                        match timeout(Duration::from_secs(5), client_clone.get(&chunk_url).send()).await {
-                            Ok(Ok(res)) if res.status().is_success() => {
-                                let bytes = res.bytes().await.unwrap();
-                                let size = bytes.len(); // Obtém o tamanho do chunk baixado
-                                
-                                let mut file = File::create(&chunk_name_clone).unwrap();
-                                file.write_all(&bytes).unwrap();
-
-                                let downloaded_checksum = compute_file_checksum(&chunk_name_clone);
-                                if downloaded_checksum != checksum {
-                                    println!("❌ Checksum inválido para '{}'. Chunk corrompido.", chunk_name_clone);
-                                    if let Err(e) = std::fs::remove_file(&chunk_name_clone) {
-                                        println!("⚠️ Erro ao remover chunk corrompido '{}': {}", chunk_name_clone, e);
-                                    }
-                                    return Err((chunk_name_clone, peer_address));
-                                }
-
-                                //println!("✅ Chunk '{}' baixado com sucesso! ({} KB)", chunk_name_clone, size / 1024);
-                                Ok((chunk_name_clone, size)) // Retorna o tamanho baixado
-                            }
-                            Ok(_) => {
-                                println!("❌ Falha ao baixar '{}'. Tentando outro peer...", chunk_name_clone);
-                                Err((chunk_name_clone, peer_address))
-                            }
-                            Err(_) => {
-                                println!("⏳ Timeout ao baixar '{}'.", chunk_name_clone);
-                                Err((chunk_name_clone, peer_address))
-                            }
-                        }
-                    }));
-                }
+        // 🔁 De tempos em tempos, procura fontes novas com os peers conhecidos (PEX)
+        if Instant::now() >= next_pex {
+            exchange_peers(state, &client, manifest, &mut chunk_map, &dropped).await;
+            next_pex = Instant::now() + PEX_INTERVAL;
+        }
+
+        // 🧭 Preenche as conexões livres com os próximos chunks escolhidos pelo escalonador
+        let free_slots = max_connections.saturating_sub(tasks.len());
+        if free_slots > 0 {
+            let now = Instant::now();
+            let waiting: HashSet<String> = missing_chunks.difference(&in_flight).cloned().collect();
+            let available: HashMap<String, Vec<String>> = chunk_map.iter()
+                .filter(|(chunk_name, _)| waiting.contains(*chunk_name))
+                .map(|(chunk_name, peers)| {
+                    let ready = peers.iter()
+                        .filter(|peer| backoff.get(*peer).is_none_or(|b| b.retry_at <= now))
+                        .cloned()
+                        .collect();
+                    (chunk_name.clone(), ready)
+                })
+                .collect();
+
+            for (chunk_name, peer_address) in scheduler.schedule(&waiting, &available, &busy, free_slots) {
+                in_flight.insert(chunk_name.clone());
+                *busy.entry(peer_address.clone()).or_default() += 1;
+                tasks.spawn(fetch_chunk(
+                    client.clone(),
+                    state.events.clone(),
+                    state.storage.clone(),
+                    state.throttle.clone(),
+                    file.clone(),
+                    chunk_name,
+                    peer_address,
+                ));
+            }
+        }
+
+        // Próximo momento em que um peer em backoff volta a ser útil para algum chunk pendente
+        let next_retry = missing_chunks.difference(&in_flight)
+            .filter_map(|chunk_name| chunk_map.get(chunk_name))
+            .flatten()
+            .filter_map(|peer| backoff.get(peer).map(|b| b.retry_at))
+            .min();
+
+        // ⏸️ Nenhum chunk restante tem fonte disponível: antes de interromper (mantendo o estado
+        // para retomar), pergunta aos peers conhecidos se há outras fontes
+        if tasks.is_empty() && next_retry.is_none() {
+            next_pex = Instant::now() + PEX_INTERVAL;
+            if exchange_peers(state, &client, manifest, &mut chunk_map, &dropped).await > 0 {
+                continue;
             }
+            return Err(P2pError::Unavailable(format!(
+                "nenhum peer disponível para {} chunk(s) de '{}'",
+                missing_chunks.len(),
+                file_name
+            )));
         }
 
-        let results = futures::future::join_all(tasks).await;
+        let result = tokio::select! {
+            Some(result) = tasks.join_next() => result,
+            _ = time::sleep_until(next_retry.unwrap_or_else(Instant::now).into()), if next_retry.is_some() => continue,
+            _ = time::sleep_until(next_pex.into()) => continue,
+        };
 
-        for result in results {
-            match result {
-                Ok(Ok((chunk_name, size))) => {
-                    missing_chunks.remove(&chunk_name);
-                    total_downloaded_bytes += size; // Soma o tamanho dos chunks baixados
+        match result {
+            Ok(Ok(download)) => {
+                in_flight.remove(&download.chunk_name);
+                release_peer(&mut busy, &download.peer_address);
+                backoff.remove(&download.peer_address);
+                missing_chunks.remove(&download.chunk_name);
+                total_downloaded_bytes += download.bytes; // Soma o tamanho dos chunks baixados
+                scheduler.record_success(&download.peer_address, download.bytes, download.elapsed);
+                state.upload_slots.record_received(&download.peer_address, download.bytes);
+                if let Some(index) = file.chunk_index(&download.chunk_name) {
+                    state.pex.record(&download.peer_address, &file, index);
                 }
-                Ok(Err((chunk_name, failed_peer))) => {
-                    println!("❌ Falha ao baixar '{}'. Removendo peer '{}'.", chunk_name, failed_peer);
-                    if let Some(peers) = chunk_map.get_mut(&chunk_name) {
-                        peers.retain(|peer| peer.peer_address != failed_peer);
+                manifest.mark_verified(&download.chunk_name, Some(&download.peer_address));
+                if let Err(e) = manifest.save(&state.storage) {
+                    println!("⚠️ Erro ao salvar o progresso do download: {}", e);
+                }
+            }
+            Ok(Err(ChunkFailure { chunk_name, peer_address, choked: Some(wait) })) => {
+                // 🚧 Choke não é falha: o chunk volta para a fila e vai para outro peer que o tenha
+                in_flight.remove(&chunk_name);
+                release_peer(&mut busy, &peer_address);
+                println!("🚧 Peer '{}' sem vagas de upload (choke). Tentando outros peers; nova tentativa nele em {}s.", peer_address, wait.as_secs());
+                backoff.entry(peer_address).or_insert(PeerBackoff { failures: 0, retry_at: Instant::now() }).retry_at = Instant::now() + wait;
+            }
+            Ok(Err(ChunkFailure { chunk_name, peer_address: failed_peer, .. })) => {
+                in_flight.remove(&chunk_name);
+                release_peer(&mut busy, &failed_peer);
+
+                let entry = backoff.entry(failed_peer.clone()).or_insert(PeerBackoff { failures: 0, retry_at: Instant::now() });
+                entry.failures += 1;
+                if entry.failures >= MAX_PEER_FAILURES {
+                    println!("🚫 Peer '{}' falhou {} vezes seguidas. Deixando de usá-lo.", failed_peer, entry.failures);
+                    backoff.remove(&failed_peer);
+                    dropped.insert(failed_peer.clone());
+                    for peers in chunk_map.values_mut() {
+                        peers.retain(|peer| *peer != failed_peer);
                     }
+                } else {
+                    let delay = (Duration::from_secs(1) * 2u32.pow(entry.failures - 1)).min(MAX_BACKOFF);
+                    entry.retry_at = Instant::now() + delay;
+                    println!("🔄 Peer '{}' em espera por {}s antes de nova tentativa.", failed_peer, delay.as_secs());
                 }
-                _ => {}
             }
-        }
-
-        if !missing_chunks.is_empty() {
-            //println!("🔄 Alguns chunks falharam no download. Tentando novamente...");
-            tokio::time::sleep(Duration::from_secs(3)).await;
+            Err(e) => {
+                println!("❌ Erro na tarefa de download: {}", e);
+                return Err(P2pError::Io(io::Error::other(e)));
+            }
         }
     }
 
@@ -410,145 +1180,440 @@ async fn download_chunks(
     ✅ Todos os chunks foram baixados!
     ==================================
     ");
+    state.events.publish(PeerEvent::DownloadComplete {
+        file: file_name.clone(),
+        bytes: total_downloaded_bytes,
+        seconds: duration,
+    });
 
-    println!("
-    ========================================================
-    🔄 Tentando reconstruir o arquivo original '{}'
-    ========================================================
-    ", file_name);
-    assemble_file(file_name);
+    println!(
+        "📊 Velocidade média do download: {:.2} KB/s ({:.2} MB/s)",
+        speed_kb_s,
+        speed_kb_s / 1024.0
+    );
+    print_rates(state);
+
+    Ok(())
+}
+
+
+/// Intervalo entre as consultas PEX durante um download
+const PEX_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Quantos peers são consultados em cada rodada PEX
+const PEX_FANOUT: usize = 8;
+
+/// **Troca de peers (PEX)**: pergunta aos peers conhecidos quem mais tem chunks do arquivo
+///
+/// Consulta as fontes do download e os peers com quem houve troca recente, e acrescenta ao
+/// `chunk_map` (e às fontes salvas no estado do download) os peers novos indicados, para que
+/// o download continue achando fontes mesmo com o tracker fora do ar. Retorna quantas
+/// fontes novas foram encontradas.
+async fn exchange_peers(
+    state: &PeerState,
+    client: &Client,
+    manifest: &mut DownloadManifest,
+    chunk_map: &mut HashMap<String, Vec<String>>,
+    ignored: &HashSet<String>,
+) -> usize {
+    let mut known: HashSet<String> = chunk_map.values().flatten().cloned().collect();
+    known.extend(state.pex.addresses());
+    if let Some(lan) = &state.lan {
+        known.extend(lan.peers_with(&manifest.file.id));
+    }
+    let targets = known.into_iter()
+        .filter(|peer| *peer != state.address && !ignored.contains(peer))
+        .choose_multiple(&mut rand::thread_rng(), PEX_FANOUT);
+
+    let file = manifest.file.clone();
+    let replies = futures::future::join_all(targets.iter().map(|peer| pex::fetch_peers(client, peer, &file.id))).await;
+
+    let mut added = 0;
+    for peer in replies.into_iter().flatten().flatten() {
+        if peer.address == state.address || ignored.contains(&peer.address) {
+            continue;
+        }
+        for pex_file in peer.files.iter().filter(|pex_file| pex_file.id == file.id) {
+            for (index, (chunk_name, hash)) in file.chunks().enumerate() {
+                if !pex_file.pieces.get(index) {
+                    continue;
+                }
+                manifest.add_source(&chunk_name, hash, &peer.address);
+                let peers = chunk_map.entry(chunk_name).or_default();
+                if !peers.contains(&peer.address) {
+                    peers.push(peer.address.clone());
+                    added += 1;
+                }
+            }
+        }
+    }
+
+    if added > 0 {
+        println!("🔁 PEX: {} nova(s) fonte(s) de chunks de '{}'", added, file.file_name);
+    }
+    added
+}
 
+/// Mostra os limites de banda e as taxas atuais de envio e recebimento
+fn print_rates(state: &PeerState) {
+    let status = state.throttle.status();
     println!(
-        "📊 Velocidade média do download: {:.2} KB/s ({:.2} MB/s)",
-        speed_kb_s,
-        speed_kb_s / 1024.0
+        "🚦 Download atual: {} (limite: {}; por peer: {}) | Upload atual: {} (limite: {}; por peer: {})",
+        throttle::format_rate(status.download_rate),
+        throttle::format_limit(status.limits.download),
+        throttle::format_limit(status.limits.peer_download),
+        throttle::format_rate(status.upload_rate),
+        throttle::format_limit(status.limits.upload),
+        throttle::format_limit(status.limits.peer_upload),
     );
-
-    Ok(())
 }
 
-
-
-
+/// Libera uma conexão com o peer no controle de conexões abertas
+fn release_peer(busy: &mut HashMap<String, usize>, peer_address: &str) {
+    if let Some(count) = busy.get_mut(peer_address) {
+        *count -= 1;
+        if *count == 0 {
+            busy.remove(peer_address);
+        }
+    }
+}
 
 /// Servidor que permite que outros peers baixem chunks deste peer
+///
+/// Só serve chunks de arquivos com manifesto local; nomes com caminho são rejeitados.
+/// O envio respeita os limites de upload global e por peer (pelo IP de quem pede).
+///
+/// Quem não tem vaga de upload recebe `503` com o cabeçalho `x-choked` e `Retry-After`
+/// (veja `choke::UploadSlots`).
+///
+/// A resposta leva no cabeçalho `x-merkle-proof` a prova de inclusão do chunk na árvore
+/// de hashes do arquivo, para que quem baixa o confira contra o ID do arquivo.
 async fn send_chunk(
+    State(state): State<SharedState>,
+    ConnectInfo(remote): ConnectInfo<SocketAddr>,
+    request_headers: HeaderMap,
     Query(params): Query<std::collections::HashMap<String, String>>,
-) -> Result<Vec<u8>, StatusCode> {
+) -> Result<(HeaderMap, Body), Response> {
     if let Some(chunk_name) = params.get("name") {
-        let mut file = match File::open(chunk_name) {
-            Ok(f) => f,
-            Err(_) => return Err(StatusCode::NOT_FOUND),
+        if !storage::is_safe_name(chunk_name) {
+            return Err(StatusCode::BAD_REQUEST.into_response());
+        }
+
+        // 📍 Localiza a peça no arquivo completo ou no arquivo parcial de um download
+        let storage = state.storage.clone();
+        let name = chunk_name.clone();
+        let found = tokio::task::spawn_blocking(move || piece_store::locate(&storage, &name)).await.ok().flatten();
+        let Some((file_manifest, index, location)) = found else {
+            return Err(StatusCode::NOT_FOUND.into_response());
         };
 
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer).unwrap();
-        Ok(buffer)
+        // 🎟️ Só atende quem tem vaga de upload; os demais recebem `choke` e o tempo até a próxima rodada
+        let announced = request_headers.get(choke::PEER_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+        // O endereço anunciado só identifica o peer se o seu host for o IP da conexão; senão
+        // qualquer um tomaria a vaga (ou o crédito de tit-for-tat) de outro peer anunciando o endereço dele
+        let confirmed = announced.filter(|address| address_matches(address, remote));
+        let identity = confirmed.clone().unwrap_or_else(|| remote.ip().to_string());
+        if let Err(wait) = state.upload_slots.request(&identity) {
+            let mut headers = HeaderMap::new();
+            headers.insert(choke::CHOKED_HEADER, HeaderValue::from_static("1"));
+            headers.insert(RETRY_AFTER, HeaderValue::from(wait.as_secs().max(1)));
+            return Err((StatusCode::SERVICE_UNAVAILABLE, headers, Json("Sem vagas de upload (choke); tente mais tarde".to_string())).into_response());
+        }
+
+        let mut file = tokio::fs::File::open(&location.path).await.map_err(|_| StatusCode::NOT_FOUND.into_response())?;
+        file.seek(io::SeekFrom::Start(location.offset)).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+        let file = file.take(location.len);
+
+        // 🌳 Anexa a prova de inclusão do chunk na árvore do arquivo
+        let mut headers = HeaderMap::new();
+        if let Some(value) = file_manifest.proof(index).and_then(|proof| HeaderValue::from_str(&manifest::encode_proof(&proof)).ok()) {
+            headers.insert(manifest::PROOF_HEADER, value);
+        }
+        // O corpo é enviado em streaming, sem carregar o chunk inteiro na memória; o balde do
+        // peer usa a mesma identificação das vagas de upload
+        let throttle = state.throttle.clone();
+        let peer = identity.clone();
+        let stream = ReaderStream::new(file).then(move |block| {
+            let throttle = throttle.clone();
+            let peer = peer.clone();
+            async move {
+                if let Ok(bytes) = &block {
+                    throttle.upload(&peer, bytes.len()).await;
+                }
+                block
+            }
+        });
+
+        // 🔁 Quem recebe o chunk inteiro passa a tê-lo: fica registrado para a troca de peers (PEX).
+        // O registro só acontece quando o corpo termina (um envio abortado ou com erro para antes)
+        // e só para endereços cujo host confere com a conexão, para que ninguém anuncie terceiros.
+        let finished = futures::stream::once(async move {
+            if let Some(address) = confirmed {
+                state.pex.record(&address, &file_manifest, index);
+            }
+            None
+        }).filter_map(futures::future::ready);
+        Ok((headers, Body::from_stream(stream.chain(finished))))
     } else {
-        Err(StatusCode::BAD_REQUEST)
+        Err(StatusCode::BAD_REQUEST.into_response())
     }
 }
 
+/// Indica se o host de um endereço anunciado (`host:porta`) é o IP da conexão
+pub(crate) fn address_matches(address: &str, remote: SocketAddr) -> bool {
+    address.parse::<SocketAddr>()
+        .is_ok_and(|announced| announced.ip().to_canonical() == remote.ip().to_canonical())
+}
+
 /// Função auxiliar para download e registro automático de arquivos
-async fn download_and_register(
-    peer_name: &str, 
-    peer_address: &str, 
-    file_name: &str,
+///
+/// Retoma o download a partir do estado salvo em `downloads/`, se existir.
+pub async fn download_and_register(
+    state: &PeerState,
+    file: FileManifest,
     max_connections: usize
 ) {
-    println!("🔄 Buscando chunks de '{}'...", file_name);
-    match get_chunks(file_name).await {
-        Ok(chunks) if chunks.is_empty() => {
-            println!("⚠️ Nenhum chunk encontrado.");
-            return;
+    if !reserve_download(state, &file.id) {
+        println!("⚠️ O download de '{}' já está em andamento.", file.file_name);
+        return;
+    }
+    download_reserved(state, file, max_connections).await;
+}
+
+/// Baixa um arquivo já reservado com `reserve_download`, liberando a reserva ao terminar
+pub async fn download_reserved(
+    state: &PeerState,
+    file: FileManifest,
+    max_connections: usize
+) {
+    let file_name = file.file_name.clone();
+    let file_id = file.id.clone();
+    let _active = ActiveDownload { state, file_id: file_id.clone() };
+
+    // ✅ O arquivo completo já está em `files/<id>/`: basta voltar a servi-lo
+    if piece_store::has_complete_file(&state.storage, &file).await {
+        println!("✅ Você já possui '{}' completo.", file_name);
+        if let Err(e) = file.save(&state.storage) {
+            println!("⚠️ Erro ao guardar o manifesto de '{}': {}", file_name, e);
+        }
+        DownloadManifest::remove(&state.storage, &file_id);
+        if let Err(e) = publish_file(state, &file).await {
+            println!("❌ Erro ao registrar '{}': {}", file_name, e);
+        }
+        return;
+    }
+
+    println!("🔄 Buscando chunks de '{}' ({})...", file_name, file_id);
+    let chunks = match get_chunks(state, &file).await {
+        Ok(chunks) => chunks,
+        Err(e) => {
+            // 🔁 Sem o tracker, o download segue com as fontes salvas e as indicadas por PEX
+            println!("⚠️ Não foi possível buscar as fontes de '{}': {}. Usando fontes conhecidas e PEX.", file_name, e);
+            vec![]
         }
+    };
 
-        Ok(chunks) => {
-            let mut missing_chunks: HashSet<ChunkRegister> = HashSet::new();
+    let mut manifest = match DownloadManifest::load(&state.storage, &file_id) {
+        Some(manifest) => {
+            println!("⏯️ Retomando download de '{}' ({}/{} chunks verificados)", file_name, manifest.verified_count(), manifest.chunks.len());
+            manifest
+        }
+        None => DownloadManifest::new(file, max_connections),
+    };
+    for chunk in &chunks {
+        manifest.add_source(&chunk.chunk_name, &chunk.checksum, &chunk.peer_address);
+    }
 
-            let local_chunks: HashSet<String> = fs::read_dir(".")
-                .unwrap()
-                .flatten()
-                .filter_map(|entry| entry.file_name().to_str().map(|s| s.to_string()))
-                .filter(|name| name.starts_with(file_name) && name.contains(".chunk"))
-                .collect();
+    // 🔍 Sem o arquivo parcial, as peças marcadas no estado salvo não existem mais
+    if !state.storage.partial_path(&file_id).exists() {
+        manifest.pieces = Bitfield::new(manifest.file.chunk_count());
+    }
+    if let Err(e) = piece_store::preallocate(&state.storage, &manifest.file).await {
+        println!("❌ Erro ao criar o arquivo parcial de '{}': {}", file_name, e);
+        return;
+    }
 
-            println!("📂 Chunks locais encontrados: {:?}", local_chunks);
+    let reused = reuse_local_chunks(state, &mut manifest).await;
+    if reused > 0 {
+        println!("♻️ {} chunk(s) de '{}' reaproveitados de outros arquivos locais.", reused, file_name);
+    }
 
-            for chunk in &chunks {
-                if !local_chunks.contains(&chunk.chunk_name) {
-                    missing_chunks.insert(chunk.clone());
-                }
+    if manifest.missing().is_empty() {
+        println!("✅ Você já possui todos os chunks de '{}'. Tentando reconstrução...", file_name);
+        if finalize_download(state, &mut manifest).await {
+            DownloadManifest::remove(&state.storage, &file_id);
+            return;
+        }
+        // Sem peças estragadas não há o que baixar de novo
+        if manifest.missing().is_empty() {
+            if let Err(e) = manifest.save(&state.storage) {
+                println!("⚠️ Erro ao salvar o estado do download: {}", e);
             }
+            return;
+        }
+    }
+    let missing = manifest.missing();
 
-            if missing_chunks.is_empty() {
-                println!("✅ Você já possui todos os chunks de '{}'. Tentando reconstrução...", file_name);
-                assemble_file(file_name);
-                return;
-            }
+    if let Err(e) = manifest.save(&state.storage) {
+        println!("⚠️ Erro ao salvar o estado do download: {}", e);
+    }
+
+    let missing_chunks: Vec<ChunkRegister> = chunks.into_iter()
+        .filter(|chunk| missing.contains(&chunk.chunk_name))
+        .collect();
+    println!("📥 Chunks faltando: {}/{}", missing.len(), manifest.chunks.len());
+
+    if let Err(e) = download_chunks(state, &mut manifest, missing_chunks, max_connections).await {
+        println!("❌ Erro ao baixar chunks: {}", e);
+        println!("⏸️ Progresso salvo ({}/{} chunks). Use 'resume' para continuar.", manifest.verified_count(), manifest.chunks.len());
+    } else if !finalize_download(state, &mut manifest).await {
+        if let Err(e) = manifest.save(&state.storage) {
+            println!("⚠️ Erro ao salvar o estado do download: {}", e);
+        }
+        println!("⏸️ Os chunks e o estado do download foram mantidos em '{}'.", state.storage.root().display());
+    } else {
+        DownloadManifest::remove(&state.storage, &file_id);
+        println!("
+        ================================================
+        ✅ Download concluído e arquivo reconstruído!
+        ================================================
+        ");
+        println!("📢 Registrando automaticamente o arquivo baixado...");
+        if let Err(e) = publish_file(state, &manifest.file).await {
+            println!("❌ Erro ao registrar '{}': {}", file_name, e);
+        }
+    }
+}
 
-            println!("📥 Chunks faltando: {:?}", missing_chunks.iter().map(|c| &c.chunk_name).collect::<Vec<_>>());
+/// **Reaproveita chunks de outros arquivos locais com o mesmo hash**
+///
+/// Com a divisão por conteúdo (`cdc`), versões editadas de um arquivo têm a maior parte
+/// dos chunks em comum; esses são lidos do disco em vez de baixados de novo.
+async fn reuse_local_chunks(state: &PeerState, manifest: &mut DownloadManifest) -> usize {
+    let storage = state.storage.clone();
+    let file_id = manifest.file.id.clone();
+    let pieces = tokio::task::spawn_blocking(move || piece_store::local_pieces(&storage, &file_id)).await.unwrap_or_default();
+
+    let mut local_pieces: HashMap<String, piece_store::PieceLocation> = HashMap::new();
+    for (hash, location) in pieces {
+        local_pieces.entry(hash).or_insert(location);
+    }
 
-            if let Err(e) = download_chunks(missing_chunks.into_iter().collect(), file_name, peer_address, max_connections).await {
-                println!("❌ Erro ao baixar chunks: {}", e);
-            } else {
-                println!("
-                ================================================
-                ✅ Download concluído e arquivo reconstruído!
-                ================================================
-                ");
-                println!("📢 Registrando automaticamente o arquivo baixado...");
-                if let Err(e) = register_chunks(peer_name, peer_address, file_name).await {
-                    println!("❌ Erro ao registrar '{}': {}", file_name, e);
+    let mut reused = 0;
+    for chunk_name in manifest.missing() {
+        let (Some(index), Some(progress)) = (manifest.file.chunk_index(&chunk_name), manifest.chunks.get(&chunk_name)) else {
+            continue;
+        };
+        let Some(location) = local_pieces.get(&progress.checksum) else {
+            continue;
+        };
+        let Ok(bytes) = piece_store::read_piece(location.clone()).await else {
+            continue;
+        };
+        if compute_checksum(bytes.clone()).await != progress.checksum {
+            continue;
+        }
+        if piece_store::write_piece(&state.storage, &manifest.file, index, bytes).await.is_ok() {
+            manifest.mark_verified(&chunk_name, None);
+            reused += 1;
+        }
+    }
+    reused
+}
+
+/// **Valida o arquivo parcial contra o manifesto e o move para `files/<id>/`**
+///
+/// Retorna `false` se o arquivo não conferir; nesse caso nada em `files/` é alterado e as
+/// peças estragadas são desmarcadas em `manifest`, para que só elas sejam baixadas de novo.
+async fn finalize_download(state: &PeerState, manifest: &mut DownloadManifest) -> bool {
+    let file_name = &manifest.file.file_name;
+    println!("
+    ========================================================
+    🔄 Tentando reconstruir o arquivo original '{}'
+    ========================================================
+    ", file_name);
+
+    match piece_store::finalize(&state.storage, &manifest.file).await {
+        Ok(checksum) => {
+            if let Err(e) = manifest.file.save(&state.storage) {
+                println!("⚠️ Erro ao guardar o manifesto de '{}': {}", file_name, e);
+            }
+            state.events.publish(PeerEvent::FileAssembled { file: file_name.clone(), checksum });
+            true
+        }
+        Err(error) => {
+            println!("❌ Não foi possível finalizar '{}': {}", file_name, error);
+            state.events.publish(PeerEvent::FileVerificationFailed { file: file_name.clone(), error: error.to_string() });
+
+            // 🩹 Confere peça a peça para baixar de novo só as que estragaram
+            match piece_store::damaged_pieces(&state.storage, manifest).await {
+                Ok(damaged) if damaged.is_empty() => {
+                    println!("⚠️ Todas as peças de '{}' conferem com os hashes; o checksum do manifesto não corresponde a elas.", file_name);
+                }
+                Ok(damaged) => {
+                    println!("🩹 {} peça(s) de '{}' não conferem e serão baixadas de novo.", damaged.len(), file_name);
+                    for index in damaged {
+                        manifest.mark_missing(index);
+                    }
                 }
+                Err(e) => println!("⚠️ Erro ao conferir as peças de '{}': {}", file_name, e),
             }
+            false
         }
-
-        Err(e) => println!("❌ Erro ao buscar arquivo '{}': {}", file_name, e),
     }
 }
 
+/// **Retoma todos os downloads não concluídos registrados em `downloads/`**
+pub async fn resume_downloads(state: &PeerState) {
+    let pending = DownloadManifest::pending(&state.storage);
+    if pending.is_empty() {
+        println!("✅ Nenhum download pendente.");
+        return;
+    }
+
+    for manifest in pending {
+        if !reserve_download(state, manifest.file_id()) {
+            continue;
+        }
+        download_reserved(state, manifest.file, manifest.max_connections).await;
+    }
+}
 
-/// Monitor de arquivos deletados
 
-#[allow(dead_code)]
 /// Monitora e remove chunks ausentes do tracker
-async fn monitor_lost_chunks(peer_name: String) {
+async fn monitor_lost_chunks(state: SharedState) {
     loop {
         time::sleep(Duration::from_secs(10)).await; // Executa a cada 10 segundos
 
-        let mut current_chunks: HashSet<String> = HashSet::new();
+        // 🔍 Lista os chunks que este peer realmente consegue servir
+        let storage = state.storage.clone();
+        let current_chunks: HashSet<String> = tokio::task::spawn_blocking(move || piece_store::available_chunks(&storage))
+            .await
+            .unwrap_or_default();
 
-        // 🔍 Lista os chunks que realmente existem no diretório do peer
-        if let Ok(entries) = fs::read_dir(".") {
-            for entry in entries.flatten() {
-                if let Some(file_name) = entry.file_name().to_str() {
-                    if file_name.contains(".chunk") {
-                        current_chunks.insert(file_name.to_string());
-                    }
-                }
+        // 🔍 Pede a cada Tracker a lista de chunks que ele acha que esse peer tem
+        let client = Client::new();
+        for tracker_url in state.trackers.ordered() {
+            let url = format!("{}/get_peer_chunks?peer={}", tracker_url, state.name);
+            let Ok(response) = client.get(&url).send().await else {
+                continue;
+            };
+            if !response.status().is_success() {
+                continue;
             }
-        }
 
-        // 🔍 Pede ao Tracker a lista de chunks que ele acha que esse peer tem
-        let client = Client::new();
-        let url = format!("http://127.0.0.1:9500/get_peer_chunks?peer={}", peer_name);
-        let res = client.get(&url).send().await;
-
-        if let Ok(response) = res {
-            if response.status().is_success() {
-                let expected_chunks: Vec<String> = response.json().await.unwrap_or_default();
-
-                for chunk in expected_chunks {
-                    if !current_chunks.contains(&chunk) {
-                        println!("🚨 Chunk '{}' foi perdido! Removendo do Tracker...", chunk);
-                        
-                        let payload = serde_json::json!({ "peer": peer_name, "chunk": chunk });
-                        let _ = client.post("http://127.0.0.1:9500/unregister_chunk")
-                            .json(&payload)
-                            .send()
-                            .await;
-                    }
+            let expected_chunks: Vec<String> = response.json().await.unwrap_or_default();
+            for chunk in expected_chunks {
+                if !current_chunks.contains(&chunk) {
+                    println!("🚨 Chunk '{}' foi perdido! Removendo do Tracker {}...", chunk, tracker_url);
+
+                    let payload = serde_json::json!({ "peer": state.name, "chunk": chunk });
+                    let _ = client.post(format!("{}/unregister_chunk", tracker_url))
+                        .json(&payload)
+                        .send()
+                        .await;
                 }
             }
         }
@@ -557,12 +1622,45 @@ async fn monitor_lost_chunks(peer_name: String) {
 
 
 
-/// Remove um arquivo do tracker
-async fn unregister_file(peer_name: &str, file_name: &str) -> Result<(), Box<dyn Error>> {
+/// Redistribui as vagas de upload a cada rodada (unchoke otimista e tit-for-tat)
+async fn rotate_upload_slots(state: SharedState) {
+    let mut interval = time::interval(choke::ROUND_INTERVAL);
+    interval.tick().await; // O primeiro tick é imediato
+    loop {
+        interval.tick().await;
+        state.upload_slots.rechoke();
+    }
+}
+
+/// **Mantém o peer na DHT**: entra na rede e republica os arquivos locais periodicamente
+///
+/// Downloads em andamento também são anunciados, já que servem as peças que têm.
+async fn maintain_dht(state: SharedState) {
+    let Some(dht) = &state.dht else {
+        return;
+    };
+    let mut interval = time::interval(dht::REPUBLISH_INTERVAL);
+    loop {
+        interval.tick().await; // O primeiro tick é imediato
+        dht.expire_records();
+        if dht.node_count() == 0 {
+            dht.bootstrap().await;
+        }
+
+        let storage = state.storage.clone();
+        let local = tokio::task::spawn_blocking(move || piece_store::local_bitfields(&storage)).await.unwrap_or_default();
+        for file_id in local.keys() {
+            dht.announce(file_id).await;
+        }
+    }
+}
+
+/// Remove um arquivo de um tracker
+async fn unregister_file(state: &PeerState, tracker_url: &str, file_id: &str) -> Result<(), P2pError> {
     let client = Client::new();
-    let payload = serde_json::json!({ "peer": peer_name, "file": file_name });
+    let payload = serde_json::json!({ "peer": state.name, "id": file_id });
 
-    let res = client.post("http://127.0.0.1:9500/unregister_file")
+    let res = client.post(format!("{}/unregister_file", tracker_url))
         .json(&payload)
         .send()
         .await?;
@@ -571,71 +1669,71 @@ async fn unregister_file(peer_name: &str, file_name: &str) -> Result<(), Box<dyn
     let response_text = res.text().await?;
 
     if status.is_success() {
-        println!("🚨 Arquivo '{}' removido do Tracker! Resposta: {}", file_name, response_text);
+        println!("🚨 Arquivo '{}' removido do Tracker {}! Resposta: {}", file_id, tracker_url, response_text);
     } else {
-        println!("❌ Falha ao remover '{}': HTTP {} - {}", file_name, status, response_text);
+        println!("❌ Falha ao remover '{}': HTTP {} - {}", file_id, status, response_text);
     }
 
     Ok(())
 }
 
 /// Remove um peer do tracker
-async fn unregister_peer(peer_name: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let client = Client::new();
-    let payload = serde_json::json!({ "peer": peer_name });
-
-    let res = client.post("http://127.0.0.1:9500/unregister_peer")
-        .json(&payload)
-        .send()
-        .await?;
-
-    if res.status().is_success() {
-        println!("👋 Peer '{}' removido do Tracker com sucesso!", peer_name);
-    } else {
-        println!("❌ Falha ao remover peer '{}'.", peer_name);
+async fn unregister_peer(state: &PeerState) -> Result<(), P2pError> {
+    if !state.tracker_enabled() {
+        return Ok(());
+    }
+    let payload = serde_json::json!({ "peer": state.name });
+
+    let mut last_error = None;
+    let mut answered = false;
+    for (tracker_url, result) in state.announce("/unregister_peer", &payload).await {
+        match result {
+            Ok(res) if res.status().is_success() => {
+                answered = true;
+                println!("👋 Peer '{}' removido do Tracker {} com sucesso!", state.name, tracker_url);
+            }
+            Ok(_) => {
+                answered = true;
+                println!("❌ Falha ao remover peer '{}' do Tracker {}.", state.name, tracker_url);
+            }
+            Err(e) => last_error = Some(e),
+        }
     }
 
-    Ok(())
+    // Sem tracker no ar, quem usa a DHT ou a rede local sai dela sozinho ao parar de responder
+    match last_error {
+        Some(e) if !answered && !state.peer_discovery() => Err(e),
+        _ => Ok(()),
+    }
 }
 
 /// Monitor de arquivos ausentes - verifica periodicamente se arquivos registrados ainda existem
-async fn monitor_missing_files(peer_name: String) {
+async fn monitor_missing_files(state: SharedState) {
     loop {
         time::sleep(Duration::from_secs(1)).await;
 
-        // Cria um conjunto com os arquivos atualmente presentes no diretório
-        let mut current_files: HashSet<String> = HashSet::new();
-        if let Ok(entries) = fs::read_dir(".") {
-            for entry in entries.flatten() {
-                if let Some(file_name) = entry.file_name().to_str() {
-                    current_files.insert(file_name.to_string());
-                }
+        // Consulta a lista de arquivos registrados em cada tracker
+        let client = Client::new();
+        for tracker_url in state.trackers.ordered() {
+            let Ok(response) = state.tracker_get(&client, &tracker_url, "/list").await else {
+                continue;
+            };
+            if !response.status().is_success() {
+                continue;
             }
-        }
+            let list: Vec<PeerInfo> = response.json().await.unwrap_or_default();
 
-        // Consulta a lista de arquivos registrados no tracker
-        let client = Client::new();
-        let url = "http://127.0.0.1:9500/list".to_string();
-        let res = client.get(&url).send().await;
-
-        if let Ok(response) = res {
-            if response.status().is_success() {
-                let list: Vec<PeerInfo> = response.json().await.unwrap_or_default();
-
-                // Verifica os arquivos registrados para este peer
-                for peer in list {
-                    if peer.name == peer_name {
-                        for file in peer.files {
-                            // Verifica se existem chunks do arquivo
-                            let has_chunks = current_files.iter().any(|f| f.starts_with(&file) && f.contains(".chunk"));
-
-                            // Se o arquivo não existe e não há chunks, remove do tracker
-                            if !current_files.contains(&file) && !has_chunks {
-                                println!("🚨 Arquivo '{}' sumiu! Removendo do Tracker...", file);
-                                if let Err(e) = unregister_file(&peer_name, &file).await {
-                                    println!("❌ Erro ao remover '{}': {}", file, e);
-                                }
-                            }
+            // Verifica os arquivos registrados para este peer
+            for peer in list.into_iter().filter(|peer| peer.name == state.name && peer.address == state.address) {
+                for file in peer.files {
+                    // Verifica se há um download do arquivo em andamento
+                    let has_pieces = state.storage.partial_path(&file.id).exists();
+
+                    // Se o arquivo não existe e não há peças baixadas, remove do tracker
+                    if !state.storage.content_path(&file.id, &file.name).exists() && !has_pieces {
+                        println!("🚨 Arquivo '{}' sumiu! Removendo do Tracker {}...", file.name, tracker_url);
+                        if let Err(e) = unregister_file(&state, &tracker_url, &file.id).await {
+                            println!("❌ Erro ao remover '{}': {}", file.name, e);
                         }
                     }
                 }
@@ -644,84 +1742,426 @@ async fn monitor_missing_files(peer_name: String) {
     }
 }
 
-/// Função principal que inicia o peer
-pub async fn start_peer() {
-    // Solicita e valida o nome do usuário
-    let mut name = String::new();
-    loop {
-        print!("Digite seu nome de usuário: ");
-        io::Write::flush(&mut io::stdout()).unwrap();
-        io::stdin().read_line(&mut name).unwrap();
-        name = name.trim().to_string();
+/// **Comando não interativo recebido pela linha de comando**
+#[derive(Debug)]
+pub enum PeerCommand {
+    Share { paths: Vec<String> },                                // Compartilha arquivos e continua servindo
+    Get { file: String, connections: Option<usize>, seed: bool }, // Baixa um arquivo (nome ou ID)
+    Import { path: String, connections: Option<usize>, seed: bool }, // Baixa a partir de um manifesto exportado
+    Export { file: String, output: Option<String> },             // Exporta o manifesto de um arquivo
+    List,                                                        // Lista peers e arquivos
+    Chat { to: String, message: String },                        // Envia uma mensagem de chat
+    Resume { seed: bool },                                       // Retoma downloads interrompidos
+}
 
-        if !name.is_empty() {
-            break;
-        }
-        println!("❌ Nome inválido. Tente novamente.");
+impl PeerCommand {
+    /// Interpreta os argumentos posicionais; `Ok(None)` indica o modo interativo
+    pub fn from_args(args: &CliArgs) -> Result<Option<PeerCommand>, String> {
+        let command = match args.positional.split_first() {
+            None => return Ok(None),
+            Some((name, rest)) => match (name.as_str(), rest) {
+                ("share", paths) if !paths.is_empty() => PeerCommand::Share { paths: paths.to_vec() },
+                ("get", [file]) => PeerCommand::Get {
+                    file: file.clone(),
+                    connections: parse_connections(args)?,
+                    seed: args.has("seed"),
+                },
+                ("import", [path]) => PeerCommand::Import {
+                    path: path.clone(),
+                    connections: parse_connections(args)?,
+                    seed: args.has("seed"),
+                },
+                ("export", [file]) => PeerCommand::Export { file: file.clone(), output: None },
+                ("export", [file, output]) => PeerCommand::Export { file: file.clone(), output: Some(output.clone()) },
+                ("list", []) => PeerCommand::List,
+                ("resume", []) => PeerCommand::Resume { seed: args.has("seed") },
+                ("chat", [to, message @ ..]) if !message.is_empty() => PeerCommand::Chat {
+                    to: to.clone(),
+                    message: message.join(" "),
+                },
+                _ => return Err(format!("Comando inválido: '{}'", args.positional.join(" "))),
+            },
+        };
+        Ok(Some(command))
     }
+}
 
-    // Gera uma porta aleatória entre 8000 e 9000
-    let port = rand::thread_rng().gen_range(8000..9000);
-    let address = format!("127.0.0.1:{}", port);
-
-    // Tenta registrar o peer no tracker
-    if !register_peer(&name, &address).await {
-        return;
+/// Lê a flag `--connections`, se informada
+fn parse_connections(args: &CliArgs) -> Result<Option<usize>, String> {
+    match args.get("connections") {
+        Some(value) => value.parse::<usize>()
+            .map(Some)
+            .map_err(|_| format!("Número de conexões inválido: '{}'", value)),
+        None => Ok(None),
     }
+}
+
+/// Monta o estado compartilhado do peer a partir da configuração
+///
+/// Sem `data_dir` configurado, cada peer usa `p2p_data/<nome>`, para que peers iniciados
+/// na mesma pasta não sobrescrevam os chunks uns dos outros.
+pub(crate) fn build_state(config: &PeerConfig, name: &str, address: &str) -> SharedState {
+    let data_dir = config.data_dir.clone()
+        .unwrap_or_else(|| Path::new(storage::DEFAULT_DATA_ROOT).join(name).to_string_lossy().to_string());
 
-    // ✅ Inicia o envio de heartbeats a cada 60 segundos
-    tokio::spawn(send_heartbeat(name.clone()));
+    Arc::new(PeerState {
+        name: name.to_string(),
+        trackers: TrackerList::new(config.tracker_tiers()),
+        address: address.to_string(),
+        downloads: Mutex::new(HashSet::new()),
+        events: EventBus::new(),
+        storage: Storage::new(data_dir),
+        scheduler: config.scheduler.clone(),
+        max_connections: config.max_connections,
+        connection_policy: config.connection_policy.clone(),
+        chunking: ChunkingOptions {
+            mode: ChunkingMode::from_name(&config.chunking).unwrap_or_default(),
+            chunk_size: config.chunk_size,
+        },
+        throttle: Arc::new(Throttle::new(config.rate_limits())),
+        upload_slots: UploadSlots::new(
+            config.upload_slots,
+            UnchokeStrategy::from_name(&config.unchoke).unwrap_or_default(),
+        ),
+        pex: PexTable::default(),
+        dht: match DhtMode::from_name(&config.dht).unwrap_or_default() {
+            DhtMode::Off => None,
+            mode => Some(Dht::new(mode, address, config.dht_bootstrap.clone())),
+        },
+        lan: config.lan.then(|| LanDiscovery::new(config.lan_group, config.advertised_host())),
+        api_origins: config.api_origins.clone(),
+        share_dirs: config.share_dirs.clone(),
+    })
+}
 
-    // Inicia os monitores de arquivos em background
-    tokio::spawn(monitor_missing_files(name.clone())); 
+/// **Registra o peer no tracker e sobe o servidor, heartbeats e monitores**
+///
+/// Retorna `None` se o registro no tracker falhar.
+async fn start_services(config: &PeerConfig, name: &str) -> Option<SharedState> {
+    // Usa a porta configurada ou gera uma porta aleatória entre 8000 e 9000
+    let port = config.port.unwrap_or_else(|| rand::thread_rng().gen_range(8000..9000));
+    let bind_address = config.bind_address(port);
+    let address = config.advertised_address(port);
 
     // Configura o estado compartilhado do peer
-    let state = Arc::new(PeerState {
-        name: name.clone(),
-        tracker_url: "http://127.0.0.1:9500".to_string(),
-        address: address.clone(),
-    });
+    let state = build_state(config, name, &address);
+    if let Err(e) = state.storage.create_dirs() {
+        println!("❌ Erro ao criar o diretório de dados '{}': {}", state.storage.root().display(), e);
+        return None;
+    }
+    println!("💾 Diretório de dados: {}", state.storage.root().display());
+
+    // Escuta antes de se registrar, para não anunciar um endereço onde ninguém responde
+    let listener = match TcpListener::bind(&bind_address).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            println!("❌ Não foi possível escutar em {}: {}", bind_address, e);
+            return None;
+        }
+    };
+
+    let pending = DownloadManifest::pending(&state.storage);
+    if !pending.is_empty() {
+        let files: Vec<&str> = pending.iter().map(|manifest| manifest.file.file_name.as_str()).collect();
+        println!("⏸️ Downloads interrompidos: {:?}. Use 'resume' para continuar.", files);
+    }
+
+    if state.tracker_enabled() {
+        // Tenta registrar o peer no tracker; com `--dht fallback` o peer segue só com a DHT se ele estiver fora do ar
+        match register_peer(&state).await {
+            Ok(true) => {}
+            Err(_) if state.peer_discovery() => {
+                println!("🌐 Seguindo com a DHT e a rede local enquanto o Tracker está fora do ar.");
+            }
+            _ => return None,
+        }
 
-    // Configura as rotas do servidor
+        // ✅ Inicia o envio de heartbeats a cada 60 segundos
+        tokio::spawn(send_heartbeat(state.clone()));
+
+        // Inicia os monitores de arquivos e chunks em background
+        tokio::spawn(monitor_missing_files(state.clone()));
+        tokio::spawn(monitor_lost_chunks(state.clone()));
+    }
+    tokio::spawn(rotate_upload_slots(state.clone()));
+
+    // Configura as rotas do servidor (P2P + API de controle local, com CORS próprio)
     let app = Router::new()
         .route("/get_chunk", get(send_chunk))
-        .route("/chat", post(chat::receive_chat)) 
+        .route("/pex", get(pex::peer_exchange))
+        .route("/manifest", get(send_manifest))
+        .route("/chat", post(chat::receive_chat))
+        .merge(dht::routes())
+        .layer(
+            CorsLayer::new()
+                .allow_origin(Any)
+                .allow_methods(Any)
+                .allow_headers(Any),
+        )
+        .merge(api::routes(&state))
         .with_state(state.clone());
-    
-    // Inicia o servidor na porta escolhida
-    let listener = TcpListener::bind(&address).await.unwrap();
-    println!("📡 Peer '{}' rodando em {}", name, address);
+
+    let tracker = if state.tracker_enabled() { format!("tracker {}", state.trackers.ordered().join(", ")) } else { "sem tracker".to_string() };
+    println!("📡 Peer '{}' rodando em {} (anunciado como {}, {})", name, bind_address, address, tracker);
 
     // Inicia o servidor em uma task separada
     tokio::spawn(async move {
-        axum::serve(listener, app).await.unwrap();
+        if let Err(e) = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await {
+            println!("❌ Servidor do peer parou: {}", e);
+        }
     });
 
-    // Verifica e compartilha automaticamente arquivos .txt existentes
-    if let Ok(entries) = fs::read_dir(".") {
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if path.is_file() {
-                if let Some(extension) = path.extension() {
-                    if extension == "txt" {
-                        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
-                        println!("📂 Arquivo encontrado: '{}' - Compartilhando automaticamente...", file_name);
-                        if let Err(e) = register_chunks(&name, &address, &file_name).await {
-                            println!("❌ Erro ao compartilhar '{}': {}", file_name, e);
-                        }
-                    }
+    // 🌐 Entra na DHT só com o servidor no ar, para que os outros nós consigam responder
+    if state.dht.is_some() {
+        tokio::spawn(maintain_dht(state.clone()));
+    }
+    if state.lan.is_some() {
+        tokio::spawn(lan::run(state.clone()));
+        time::sleep(lan::SETTLE_TIME).await; // Respostas dos peers da rede local ao primeiro beacon
+    }
+
+    Some(state)
+}
+
+/// Verifica e compartilha automaticamente arquivos .txt existentes no diretório de arquivos
+async fn share_existing_txt_files(state: &PeerState) {
+    for file_name in state.storage.list_files() {
+        if file_name.ends_with(".txt") {
+            println!("📂 Arquivo encontrado: '{}' - Compartilhando automaticamente...", file_name);
+            if let Err(e) = register_chunks(state, &state.storage.file_path(&file_name).to_string_lossy()).await {
+                println!("❌ Erro ao compartilhar '{}': {}", file_name, e);
+            }
+        }
+    }
+}
+
+/// **Resolve o destinatário do chat: aceita um endereço `host:porta` ou o nome de um peer**
+pub async fn resolve_peer_address(state: &PeerState, to: &str) -> Result<String, P2pError> {
+    if to.contains(':') {
+        return Ok(to.to_string());
+    }
+    if let Some(address) = state.lan.as_ref().and_then(|lan| lan.address_of(to)) {
+        return Ok(address);
+    }
+
+    let list = state.first_answer::<Vec<PeerInfo>>("/list", |list| list.iter().any(|peer| peer.name == to)).await?;
+
+    list.unwrap_or_default()
+        .into_iter()
+        .find(|peer| peer.name == to)
+        .map(|peer| peer.address)
+        .ok_or_else(|| P2pError::NotFound(format!("Peer '{}' não encontrado no Tracker", to)))
+}
+
+/// Mantém o peer servindo chunks até receber Ctrl+C e então sai da rede
+async fn serve_until_shutdown(state: &PeerState) {
+    println!("🟢 Peer '{}' ativo. Pressione Ctrl+C para sair.", state.name);
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        println!("⚠️ Erro ao aguardar Ctrl+C: {}", e);
+    }
+
+    println!("👋 Saindo...");
+    if let Err(e) = unregister_peer(state).await {
+        println!("❌ Erro ao remover peer: {}", e);
+    }
+}
+
+/// **Executa um comando sem interação com o usuário (sem prompts nem diálogo de arquivos)**
+pub async fn run_command(config: PeerConfig, command: PeerCommand) {
+    let name = config.name.clone();
+
+    match command {
+        PeerCommand::List => {
+            let state = build_state(&config, name.as_deref().unwrap_or_default(), "");
+            if let Some(lan) = &state.lan {
+                // Sem servidor rodando, a tabela da rede local vem de ouvir os beacons por um tempo
+                println!("📶 Ouvindo a rede local por {}s...", lan::BEACON_INTERVAL.as_secs() + 1);
+                if let Err(e) = lan.listen(lan::BEACON_INTERVAL + Duration::from_secs(1)).await {
+                    println!("⚠️ Erro ao ouvir a rede local: {}", e);
+                }
+            }
+            if let Err(e) = list_peers(&state).await {
+                println!("❌ Erro ao listar peers: {}", e);
+            }
+        }
+
+        PeerCommand::Chat { to, message } => {
+            let Some(name) = name else {
+                println!("❌ Informe o remetente com --name para enviar mensagens.");
+                return;
+            };
+            let state = build_state(&config, &name, "");
+
+            let recipient_address = match resolve_peer_address(&state, &to).await {
+                Ok(address) => address,
+                Err(e) => {
+                    println!("❌ Erro ao localizar '{}': {}", to, e);
+                    return;
+                }
+            };
+
+            let chat_message = chat::ChatMessage {
+                sender: name,
+                message,
+                timestamp: chat::current_timestamp(),
+            };
+
+            if let Err(e) = chat::send_chat_message(&recipient_address, chat_message).await {
+                println!("❌ Erro ao enviar a mensagem: {}", e);
+            }
+        }
+
+        PeerCommand::Share { paths } => {
+            let Some(name) = name else {
+                println!("❌ Informe o nome do peer com --name.");
+                return;
+            };
+            let Some(state) = start_services(&config, &name).await else {
+                return;
+            };
+
+            share_existing_txt_files(&state).await;
+            for path in paths {
+                if let Err(e) = register_chunks(&state, &path).await {
+                    println!("❌ Erro ao compartilhar arquivo '{}': {}", path, e);
+                }
+            }
+
+            serve_until_shutdown(&state).await;
+        }
+
+        PeerCommand::Get { file, connections, seed } => {
+            let Some(name) = name else {
+                println!("❌ Informe o nome do peer com --name.");
+                return;
+            };
+            let Some(state) = start_services(&config, &name).await else {
+                return;
+            };
+
+            match resolve_file(&state, &file).await {
+                Ok(manifest) => download_with_connections(&state, manifest, connections).await,
+                Err(e) => println!("❌ Erro ao localizar '{}': {}", file, e),
+            }
+
+            seed_or_leave(&state, seed).await;
+        }
+
+        PeerCommand::Import { path, connections, seed } => {
+            let manifest = match FileManifest::import(&path) {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    println!("❌ {}", e);
+                    return;
                 }
+            };
+            let Some(name) = name else {
+                println!("❌ Informe o nome do peer com --name.");
+                return;
+            };
+            let Some(state) = start_services(&config, &name).await else {
+                return;
+            };
+
+            println!("📥 Manifesto importado: '{}' ({})", manifest.file_name, manifest.id);
+            download_with_connections(&state, manifest, connections).await;
+
+            seed_or_leave(&state, seed).await;
+        }
+
+        PeerCommand::Export { file, output } => {
+            let state = build_state(&config, name.as_deref().unwrap_or_default(), "");
+
+            // Usa o manifesto local, se o peer já tiver o arquivo; senão, pede ao tracker
+            let manifest = match FileManifest::find_local(&state.storage, &file) {
+                Some(manifest) => manifest,
+                None => match resolve_file(&state, &file).await {
+                    Ok(manifest) => manifest,
+                    Err(e) => {
+                        println!("❌ Erro ao localizar '{}': {}", file, e);
+                        return;
+                    }
+                },
+            };
+
+            let output = output.unwrap_or_else(|| format!("{}.{}", manifest.file_name, manifest::MANIFEST_EXTENSION));
+            match manifest.export(&output) {
+                Ok(()) => println!("📝 Manifesto de '{}' ({}) exportado para '{}'", manifest.file_name, manifest.id, output),
+                Err(e) => println!("❌ Erro ao exportar o manifesto para '{}': {}", output, e),
             }
         }
+
+        PeerCommand::Resume { seed } => {
+            let Some(name) = name else {
+                println!("❌ Informe o nome do peer com --name.");
+                return;
+            };
+            let Some(state) = start_services(&config, &name).await else {
+                return;
+            };
+
+            resume_downloads(&state).await;
+
+            seed_or_leave(&state, seed).await;
+        }
+    }
+}
+
+/// Baixa um arquivo respeitando o limite de conexões do peer
+async fn download_with_connections(state: &PeerState, manifest: FileManifest, connections: Option<usize>) {
+    let chosen_connections = choose_connections(state, connections);
+    if connections.is_some_and(|requested| requested != chosen_connections) {
+        println!("⚠️ Número de conexões deve estar entre 1 e {}. Usando {}.", state.max_connections, chosen_connections);
     }
 
+    println!("🔄 Iniciando download com {} conexões paralelas...", chosen_connections);
+    download_and_register(state, manifest, chosen_connections).await;
+}
+
+/// Ao fim de um comando, continua servindo (`--seed`) ou sai da rede
+async fn seed_or_leave(state: &PeerState, seed: bool) {
+    if seed {
+        serve_until_shutdown(state).await;
+    } else if let Err(e) = unregister_peer(state).await {
+        println!("❌ Erro ao remover peer: {}", e);
+    }
+}
+
+/// Função principal que inicia o peer
+pub async fn start_peer(config: PeerConfig) {
+    // Usa o nome configurado ou solicita e valida o nome do usuário
+    let name = match config.name.clone() {
+        Some(name) => name,
+        None => {
+            let mut name = String::new();
+            loop {
+                print!("Digite seu nome de usuário: ");
+                io::Write::flush(&mut io::stdout()).unwrap();
+                io::stdin().read_line(&mut name).unwrap();
+                name = name.trim().to_string();
+
+                if !name.is_empty() {
+                    break;
+                }
+                println!("❌ Nome inválido. Tente novamente.");
+            }
+            name
+        }
+    };
+
+    let Some(state) = start_services(&config, &name).await else {
+        return;
+    };
+
+    share_existing_txt_files(&state).await;
+
     // Loop principal de comandos
     loop {
-        println!("\n📜 Comandos: share | get | list | chat | exit");
+        println!("\n📜 Comandos: share | get | resume | list | chat | limit | exit");
 
         let mut command = String::new();
         io::stdin().read_line(&mut command).unwrap();
-        let parts: Vec<&str> = command.trim().split_whitespace().collect();
+        let parts: Vec<&str> = command.split_whitespace().collect();
 
         // Processa os comandos do usuário
         match parts.as_slice() {
@@ -754,7 +2194,7 @@ pub async fn start_peer() {
                 // Abre o explorador de arquivos para seleção
                 if let Some(file_path) = select_file() {
                     println!("📂 Arquivo selecionado: {}", file_path);
-                    if let Err(e) = register_chunks(&name, &address, &file_path).await {
+                    if let Err(e) = register_chunks(&state, &file_path).await {
                         println!("❌ Erro ao compartilhar arquivo '{}': {}", file_path, e);
                     }
                 } else {
@@ -765,10 +2205,10 @@ pub async fn start_peer() {
 
             // Comando para baixar arquivo (sem nome do arquivo)
             ["get"] => {
-                let max_allowed = determine_max_connections();
+                let max_allowed = state.max_connections;
             
-                println!("🔄 Você tem {} chunks. Seu limite de conexões paralelas é: {}", count_local_chunks(), max_allowed);
-                println!("Digite o nome do arquivo que deseja baixar:");
+                println!("🔄 Você tem {} chunks. Seu limite de conexões paralelas é: {}", count_local_chunks(&state.storage), max_allowed);
+                println!("Digite o nome ou o ID do arquivo que deseja baixar:");
             
                 let mut file_name = String::new();
                 io::stdin().read_line(&mut file_name).unwrap();
@@ -795,22 +2235,45 @@ pub async fn start_peer() {
                     }
                 }
             
-                println!("🔄 Iniciando download com {} conexões paralelas...", chosen_connections);
-                download_and_register(&name, &address, &file_name, chosen_connections).await;
+                match resolve_file(&state, &file_name).await {
+                    Ok(manifest) => {
+                        println!("🔄 Iniciando download com {} conexões paralelas...", chosen_connections);
+                        download_and_register(&state, manifest, chosen_connections).await;
+                    }
+                    Err(e) => println!("❌ Erro ao localizar '{}': {}", file_name, e),
+                }
             }
             
 
+            // Comando para retomar downloads interrompidos
+            ["resume"] => {
+                resume_downloads(&state).await;
+            }
+
             // Comando para listar peers e arquivos
             ["list"] => {
-                if let Err(e) = list_peers().await {
+                if let Err(e) = list_peers(&state).await {
                     println!("❌ Erro ao listar peers: {}", e);
                 }
             }
 
+            // Comando para ver ou alterar os limites de banda
+            ["limit"] => print_rates(&state),
+            ["limit", name, value] => {
+                let mut limits = state.throttle.limits();
+                match throttle::parse_rate(value).and_then(|rate| limits.set(name, rate)) {
+                    Ok(()) => {
+                        state.throttle.set_limits(limits);
+                        print_rates(&state);
+                    }
+                    Err(e) => println!("❌ {}", e),
+                }
+            }
+
             // Comando para sair do programa
             ["exit"] => {
                 println!("👋 Saindo...");
-                if let Err(e) = unregister_peer(&name).await {
+                if let Err(e) = unregister_peer(&state).await {
                     println!("❌ Erro ao remover peer: {}", e);
                 }
                 break;
@@ -0,0 +1,119 @@
+use axum::extract::{Json, Query, State};
+use reqwest::Client;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::time::{timeout, Duration, Instant};
+
+use crate::manifest::FileManifest;
+use crate::peer::SharedState;
+use crate::piece_store::{self, Bitfield};
+
+/// Por quanto tempo um peer com quem houve troca de chunks continua sendo indicado
+const PEX_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Tempo máximo de espera pela resposta de `/pex`
+const PEX_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// **Peças de um arquivo que um peer tem**
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PexFile {
+    pub id: String,       // ID do arquivo
+    pub pieces: Bitfield, // Chunks presentes (ou, para peers vistos, os trocados com eles)
+}
+
+/// **Peer indicado por outro peer na troca de peers (PEX)**
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PexPeer {
+    pub address: String,      // Endereço anunciado do peer
+    pub files: Vec<PexFile>,  // Arquivos e chunks que ele tem
+    pub last_seen: u64,       // Segundos desde a última troca de chunks com ele
+}
+
+/// Parâmetros de `/pex`
+#[derive(Debug, Deserialize)]
+pub struct PexQuery {
+    id: Option<String>, // Restringe a resposta a um arquivo
+}
+
+/// Peer com quem este peer trocou chunks recentemente
+struct SeenPeer {
+    last_seen: Instant,
+    files: HashMap<String, Bitfield>,
+}
+
+/// **Tabela de peers com quem houve troca de chunks**
+///
+/// Alimentada pelos chunks enviados em `/get_chunk` e pelos baixados em `download_chunks`.
+/// Para cada peer guarda os chunks trocados com ele: quem nos enviou um chunk o tem, e quem
+/// nos pediu um chunk passa a tê-lo (downloads em andamento também servem suas peças).
+#[derive(Default)]
+pub struct PexTable {
+    peers: Mutex<HashMap<String, SeenPeer>>,
+}
+
+impl PexTable {
+    /// Registra que o chunk `index` de `file` foi trocado com `peer`
+    pub fn record(&self, peer: &str, file: &FileManifest, index: usize) {
+        let mut peers = self.peers.lock().unwrap();
+        let seen = peers.entry(peer.to_string()).or_insert_with(|| SeenPeer { last_seen: Instant::now(), files: HashMap::new() });
+        seen.last_seen = Instant::now();
+        seen.files.entry(file.id.clone()).or_insert_with(|| Bitfield::new(file.chunk_count())).set(index);
+    }
+
+    /// Peers vistos dentro de `PEX_TTL`, opcionalmente só os que têm chunks de `file_id`
+    pub fn recent(&self, file_id: Option<&str>) -> Vec<PexPeer> {
+        let mut peers = self.peers.lock().unwrap();
+        peers.retain(|_, seen| seen.last_seen.elapsed() <= PEX_TTL);
+
+        peers.iter()
+            .map(|(address, seen)| PexPeer {
+                address: address.clone(),
+                files: seen.files.iter()
+                    .filter(|(id, _)| file_id.is_none_or(|file_id| *id == file_id))
+                    .map(|(id, pieces)| PexFile { id: id.clone(), pieces: pieces.clone() })
+                    .collect(),
+                last_seen: seen.last_seen.elapsed().as_secs(),
+            })
+            .filter(|peer| !peer.files.is_empty())
+            .collect()
+    }
+
+    /// Endereços de todos os peers vistos recentemente
+    pub fn addresses(&self) -> Vec<String> {
+        self.recent(None).into_iter().map(|peer| peer.address).collect()
+    }
+}
+
+/// **Troca de peers (`GET /pex?id=<arquivo>`)**
+///
+/// Responde com os peers com quem este peer trocou chunks recentemente e o que cada um
+/// tem, mais o próprio peer com as peças que tem agora. Permite continuar achando fontes
+/// quando o tracker está fora do ar.
+pub async fn peer_exchange(
+    State(state): State<SharedState>,
+    Query(query): Query<PexQuery>,
+) -> Json<Vec<PexPeer>> {
+    let mut peers = state.pex.recent(query.id.as_deref());
+
+    let storage = state.storage.clone();
+    let local = tokio::task::spawn_blocking(move || piece_store::local_bitfields(&storage)).await.unwrap_or_default();
+    let files: Vec<PexFile> = local.into_iter()
+        .filter(|(id, _)| query.id.as_ref().is_none_or(|file_id| id == file_id))
+        .map(|(id, pieces)| PexFile { id, pieces })
+        .collect();
+    if !files.is_empty() {
+        peers.push(PexPeer { address: state.address.clone(), files, last_seen: 0 });
+    }
+
+    Json(peers)
+}
+
+/// Pede a um peer a lista PEX de um arquivo; `None` se o peer não responder
+pub async fn fetch_peers(client: &Client, peer_address: &str, file_id: &str) -> Option<Vec<PexPeer>> {
+    let url = format!("http://{}/pex?id={}", peer_address, file_id);
+    let request = async {
+        client.get(&url).send().await?.error_for_status()?.json::<Vec<PexPeer>>().await
+    };
+    timeout(PEX_TIMEOUT, request).await.ok()?.ok()
+}
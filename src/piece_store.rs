@@ -0,0 +1,343 @@
+use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use crate::download_state::DownloadManifest;
+use crate::error::{P2pError, P2pResult};
+use crate::file_utils::{blocking, compute_checksum, compute_file_checksum};
+use crate::manifest::FileManifest;
+use crate::storage::Storage;
+
+// Os chunks não existem como arquivos separados: um arquivo compartilhado é servido por
+// posição direto de `files/<id>/<nome>`, e um download grava cada peça na sua posição dentro de
+// `downloads/<id>.part`, criado esparso com o tamanho final. O bitfield do estado do
+// download indica quais peças do arquivo parcial já foram gravadas e verificadas.
+
+/// **Bitfield das peças (chunks) presentes de um arquivo**
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bitfield {
+    len: usize,
+    #[serde(with = "hex_bits")]
+    bits: Vec<u8>, // Um bit por peça, do mais significativo para o menos significativo
+}
+
+impl Bitfield {
+    pub fn new(len: usize) -> Self {
+        Bitfield { len, bits: vec![0; len.div_ceil(8)] }
+    }
+
+    /// Bitfield com todas as peças presentes
+    pub fn full(len: usize) -> Self {
+        let mut bitfield = Bitfield::new(len);
+        (0..len).for_each(|index| bitfield.set(index));
+        bitfield
+    }
+
+    pub fn get(&self, index: usize) -> bool {
+        // Bitfields recebidos de outros peers podem ter menos bytes que `len`
+        index < self.len && self.bits.get(index / 8).is_some_and(|byte| byte & (0x80 >> (index % 8)) != 0)
+    }
+
+    pub fn set(&mut self, index: usize) {
+        if index < self.len && index / 8 < self.bits.len() {
+            self.bits[index / 8] |= 0x80 >> (index % 8);
+        }
+    }
+
+    pub fn clear(&mut self, index: usize) {
+        if index < self.len && index / 8 < self.bits.len() {
+            self.bits[index / 8] &= !(0x80 >> (index % 8));
+        }
+    }
+
+    /// Quantidade de peças presentes
+    pub fn count(&self) -> usize {
+        self.bits.iter().map(|byte| byte.count_ones() as usize).sum()
+    }
+}
+
+/// Serializa os bits como texto hexadecimal no JSON do estado do download
+mod hex_bits {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bits: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&bits.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        (0..text.len())
+            .step_by(2)
+            .map(|i| text.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| serde::de::Error::custom("bitfield inválido"))
+    }
+}
+
+/// **Onde os bytes de um chunk estão no disco**
+#[derive(Debug, Clone)]
+pub struct PieceLocation {
+    pub path: PathBuf,
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// Arquivo completo de um manifesto em `files/<id>/`, se existir com o tamanho esperado
+fn complete_file(storage: &Storage, manifest: &FileManifest) -> Option<PathBuf> {
+    let path = storage.content_path(&manifest.id, &manifest.file_name);
+    let len = fs::metadata(&path).ok()?.len();
+    (len == manifest.size).then_some(path)
+}
+
+/// Indica se o arquivo completo já está em `files/` e confere com o manifesto
+pub async fn has_complete_file(storage: &Storage, manifest: &FileManifest) -> bool {
+    let Some(path) = complete_file(storage, manifest) else {
+        return false;
+    };
+    compute_file_checksum(path).await.is_ok_and(|checksum| checksum == manifest.checksum)
+}
+
+/// Manifestos dos arquivos completos deste peer, ordenados pelo nome
+pub fn complete_files(storage: &Storage) -> Vec<FileManifest> {
+    let mut files: Vec<FileManifest> = FileManifest::list(storage)
+        .into_iter()
+        .filter(|manifest| complete_file(storage, manifest).is_some())
+        .collect();
+    files.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+    files
+}
+
+/// **Encontra um chunk local pelo nome**
+///
+/// Procura primeiro no arquivo completo e depois no arquivo parcial de um download em
+/// andamento, desde que a peça já esteja marcada no bitfield.
+pub fn locate(storage: &Storage, chunk_name: &str) -> Option<(FileManifest, usize, PieceLocation)> {
+    let (file_id, index) = crate::manifest::parse_chunk_name(chunk_name)?;
+
+    if let Some(manifest) = FileManifest::load(storage, file_id) {
+        if let (Some(path), Some((offset, len))) = (complete_file(storage, &manifest), manifest.chunk_range(index)) {
+            return Some((manifest, index, PieceLocation { path, offset, len }));
+        }
+    }
+
+    let download = DownloadManifest::load(storage, file_id)?;
+    let (offset, len) = download.file.chunk_range(index)?;
+    if !download.pieces.get(index) {
+        return None;
+    }
+    Some((download.file, index, PieceLocation { path: storage.partial_path(file_id), offset, len }))
+}
+
+/// **Nomes de todos os chunks que este peer pode servir**
+pub fn available_chunks(storage: &Storage) -> HashSet<String> {
+    let mut available = HashSet::new();
+    for manifest in FileManifest::list(storage) {
+        if complete_file(storage, &manifest).is_some() {
+            available.extend(manifest.chunks().map(|(chunk_name, _)| chunk_name));
+        }
+    }
+    for download in DownloadManifest::pending(storage) {
+        available.extend(download.present());
+    }
+    available
+}
+
+/// **Peças que este peer tem de cada arquivo**, por ID (arquivos completos e downloads em andamento)
+pub fn local_bitfields(storage: &Storage) -> HashMap<String, Bitfield> {
+    let mut bitfields = HashMap::new();
+    for manifest in FileManifest::list(storage) {
+        if complete_file(storage, &manifest).is_some() {
+            bitfields.insert(manifest.id.clone(), Bitfield::full(manifest.chunk_count()));
+        }
+    }
+    for download in DownloadManifest::pending(storage) {
+        bitfields.entry(download.file.id.clone()).or_insert(download.pieces);
+    }
+    bitfields
+}
+
+/// Peças presentes em arquivos completos, por hash, para reaproveitar em outros downloads
+pub fn local_pieces(storage: &Storage, except_id: &str) -> Vec<(String, PieceLocation)> {
+    FileManifest::list(storage)
+        .into_iter()
+        .filter(|manifest| manifest.id != except_id)
+        .filter_map(|manifest| Some((complete_file(storage, &manifest)?, manifest)))
+        .flat_map(|(path, manifest)| {
+            (0..manifest.chunk_count())
+                .filter_map(|index| {
+                    let (offset, len) = manifest.chunk_range(index)?;
+                    Some((manifest.chunk_hashes[index].clone(), PieceLocation { path: path.clone(), offset, len }))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// **Cria o arquivo parcial de um download já com o tamanho final** (esparso, sem ocupar disco)
+pub async fn preallocate(storage: &Storage, manifest: &FileManifest) -> P2pResult<()> {
+    let path = storage.partial_path(&manifest.id);
+    let size = manifest.size;
+    blocking(move || {
+        let file = OpenOptions::new().create(true).truncate(false).write(true).open(&path)?;
+        if file.metadata()?.len() != size {
+            file.set_len(size)?;
+        }
+        Ok(())
+    }).await
+}
+
+/// **Grava uma peça verificada na sua posição dentro do arquivo parcial**
+pub async fn write_piece(storage: &Storage, manifest: &FileManifest, index: usize, bytes: impl AsRef<[u8]> + Send + 'static) -> P2pResult<()> {
+    let (offset, len) = manifest.chunk_range(index)
+        .ok_or_else(|| P2pError::Protocol(format!("chunk {} fora do arquivo '{}'", index, manifest.file_name)))?;
+    if bytes.as_ref().len() as u64 != len {
+        return Err(P2pError::Protocol(format!("chunk {} com {} bytes, esperado {}", index, bytes.as_ref().len(), len)));
+    }
+
+    let path = storage.partial_path(&manifest.id);
+    blocking(move || {
+        let mut file = OpenOptions::new().write(true).open(&path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(bytes.as_ref())?;
+        Ok(())
+    }).await
+}
+
+/// Lê os bytes de uma peça local
+pub async fn read_piece(location: PieceLocation) -> P2pResult<Vec<u8>> {
+    blocking(move || {
+        let mut file = File::open(&location.path)?;
+        file.seek(SeekFrom::Start(location.offset))?;
+        let mut buffer = vec![0; location.len as usize];
+        file.read_exact(&mut buffer)?;
+        Ok(buffer)
+    }).await
+}
+
+/// **Peças marcadas no bitfield cujo conteúdo no arquivo parcial não confere com o manifesto**
+///
+/// Usado quando o arquivo inteiro não passa na verificação final, para baixar de novo só as
+/// peças estragadas (por exemplo, alteradas em disco depois de gravadas).
+pub async fn damaged_pieces(storage: &Storage, download: &DownloadManifest) -> P2pResult<Vec<usize>> {
+    let file = &download.file;
+    let path = storage.partial_path(&file.id);
+    let mut damaged = vec![];
+    for index in (0..file.chunk_count()).filter(|index| download.pieces.get(*index)) {
+        let Some((offset, len)) = file.chunk_range(index) else {
+            continue;
+        };
+        let bytes = read_piece(PieceLocation { path: path.clone(), offset, len }).await?;
+        if compute_checksum(bytes).await != file.chunk_hashes[index] {
+            damaged.push(index);
+        }
+    }
+    Ok(damaged)
+}
+
+/// **Conclui um download: confere o arquivo parcial e o move para `files/<id>/<nome>`**
+///
+/// Se o tamanho ou o checksum não conferirem com o manifesto, nada em `files/` é alterado
+/// e o arquivo parcial continua no lugar para reparo. O destino é separado pelo ID, então
+/// um arquivo do usuário com o mesmo nome nunca é substituído. Retorna o checksum do arquivo.
+pub async fn finalize(storage: &Storage, manifest: &FileManifest) -> P2pResult<String> {
+    let partial_path = storage.partial_path(&manifest.id);
+    let final_path = storage.content_path(&manifest.id, &manifest.file_name);
+
+    let size = tokio::fs::metadata(&partial_path).await?.len();
+    let checksum = compute_file_checksum(&partial_path).await?;
+    println!("🔍 Checksum do arquivo baixado: {}", checksum);
+    println!("🔍 Checksum esperado: {}", manifest.checksum);
+
+    let problem = if size != manifest.size {
+        Some(format!("tamanho {} bytes, esperado {}", size, manifest.size))
+    } else if checksum != manifest.checksum {
+        Some("checksum diferente do manifesto".to_string())
+    } else {
+        None
+    };
+    if let Some(detail) = problem {
+        println!("❌ Arquivo '{}' não confere ({}). Os dados baixados foram mantidos para reparo.", manifest.file_name, detail);
+        return Err(P2pError::ChecksumMismatch { file: manifest.file_name.clone(), detail });
+    }
+
+    // 🚀 Move para a pasta de arquivos completos, com fallback caso ocorra erro
+    tokio::fs::create_dir_all(storage.content_dir(&manifest.id)).await?;
+    if let Err(e) = tokio::fs::rename(&partial_path, &final_path).await {
+        println!("❌ Erro ao mover '{}': {}. Tentando copiar o arquivo...", partial_path.display(), e);
+        tokio::fs::copy(&partial_path, &final_path).await?;
+        let _ = tokio::fs::remove_file(&partial_path).await;
+    }
+    println!("✅ O arquivo foi validado e movido para '{}'", final_path.display());
+
+    Ok(checksum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bitfield_sets_and_counts_pieces() {
+        let mut bitfield = Bitfield::new(10);
+        assert_eq!(bitfield.count(), 0);
+
+        bitfield.set(0);
+        bitfield.set(9);
+        bitfield.set(9);
+        bitfield.set(10); // Fora do arquivo: ignorada
+        assert!(bitfield.get(0) && bitfield.get(9));
+        assert!(!bitfield.get(1) && !bitfield.get(10));
+        assert_eq!(bitfield.count(), 2);
+
+        assert_eq!(Bitfield::full(10).count(), 10);
+        assert_eq!(Bitfield::full(16).count(), 16);
+    }
+
+    #[test]
+    fn bitfield_round_trips_as_hex() {
+        let mut bitfield = Bitfield::new(12);
+        bitfield.set(0);
+        bitfield.set(11);
+
+        let json = serde_json::to_string(&bitfield).unwrap();
+        assert_eq!(json, r#"{"len":12,"bits":"8010"}"#);
+        assert_eq!(serde_json::from_str::<Bitfield>(&json).unwrap(), bitfield);
+    }
+
+    #[test]
+    fn short_bitfield_from_peer_is_not_out_of_bounds() {
+        let mut bitfield: Bitfield = serde_json::from_str(r#"{"len":20,"bits":"ff"}"#).unwrap();
+        assert!(bitfield.get(7));
+        assert!(!bitfield.get(8) && !bitfield.get(19));
+
+        bitfield.set(15);
+        assert_eq!(bitfield.count(), 8);
+    }
+
+    #[tokio::test]
+    async fn only_damaged_pieces_are_reported() {
+        let storage = Storage::new(std::env::temp_dir().join(format!("p2p-damaged-{}", std::process::id())));
+        storage.create_dirs().unwrap();
+
+        let pieces: Vec<Vec<u8>> = (0..3u8).map(|index| vec![index; 4]).collect();
+        let mut hashes = vec![];
+        for piece in &pieces {
+            hashes.push(compute_checksum(piece.clone()).await);
+        }
+        let manifest = FileManifest::new("dados.bin", 12, 4, crate::chunking::ChunkingMode::Fixed, vec![], hashes, "checksum".to_string());
+        let mut download = DownloadManifest::new(manifest.clone(), 1);
+
+        preallocate(&storage, &manifest).await.unwrap();
+        write_piece(&storage, &manifest, 0, pieces[0].clone()).await.unwrap();
+        write_piece(&storage, &manifest, 1, vec![9; 4]).await.unwrap(); // Estragada no disco
+        download.pieces.set(0);
+        download.pieces.set(1);
+
+        // A peça 2 não está marcada e não é conferida
+        assert_eq!(damaged_pieces(&storage, &download).await.unwrap(), vec![1]);
+
+        fs::remove_dir_all(storage.root()).unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+use rand::prelude::SliceRandom;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// Estratégias disponíveis, na forma aceita por `--scheduler`
+pub const SCHEDULERS: &[&str] = &["rarest", "random"];
+
+/// Peso da última medição na média móvel de vazão de cada peer
+const THROUGHPUT_WEIGHT: f64 = 0.3;
+
+/// **Estratégia de escolha de chunks e peers durante um download**
+///
+/// Sempre que há conexões livres, o download informa os chunks que aguardam, os peers
+/// disponíveis para cada um e quantas conexões já estão abertas com cada peer; o
+/// escalonador devolve os pares `(chunk, peer)` a baixar.
+pub trait ChunkScheduler: Send {
+    /// Escolhe até `limit` chunks pendentes e o peer de onde cada um será baixado
+    fn schedule(
+        &mut self,
+        missing: &HashSet<String>,
+        holders: &HashMap<String, Vec<String>>,
+        busy: &HashMap<String, usize>,
+        limit: usize,
+    ) -> Vec<(String, String)>;
+
+    /// Informa um chunk baixado com sucesso, para estimar a vazão do peer
+    fn record_success(&mut self, _peer: &str, _bytes: usize, _elapsed: Duration) {}
+}
+
+/// **Cria o escalonador pelo nome configurado** (`rarest` ou `random`)
+pub fn new_scheduler(name: &str) -> Box<dyn ChunkScheduler> {
+    match name {
+        "random" => Box::new(RandomScheduler),
+        _ => Box::new(RarestFirstScheduler::default()),
+    }
+}
+
+/// **Ordem e peers aleatórios** (comportamento original)
+pub struct RandomScheduler;
+
+impl ChunkScheduler for RandomScheduler {
+    fn schedule(
+        &mut self,
+        missing: &HashSet<String>,
+        holders: &HashMap<String, Vec<String>>,
+        _busy: &HashMap<String, usize>,
+        limit: usize,
+    ) -> Vec<(String, String)> {
+        let mut rng = rand::thread_rng();
+        let mut chunks: Vec<&String> = missing.iter().collect();
+        chunks.shuffle(&mut rng);
+
+        chunks.into_iter()
+            .filter_map(|chunk| {
+                let peer = holders.get(chunk)?.choose(&mut rng)?;
+                Some((chunk.clone(), peer.clone()))
+            })
+            .take(limit)
+            .collect()
+    }
+}
+
+/// **Chunks mais raros primeiro, distribuindo a carga entre os peers mais rápidos**
+///
+/// Os chunks com menos peers são baixados antes, para que não se percam se esses
+/// peers saírem da rede. Cada pedido vai para o peer com menos conexões abertas;
+/// entre os empatados, ganha o de maior vazão medida (peers ainda não medidos são
+/// experimentados primeiro).
+#[derive(Default)]
+pub struct RarestFirstScheduler {
+    throughput: HashMap<String, f64>, // Vazão média de cada peer, em bytes/s
+}
+
+impl RarestFirstScheduler {
+    fn score(&self, peer: &str) -> f64 {
+        self.throughput.get(peer).copied().unwrap_or(f64::INFINITY)
+    }
+}
+
+impl ChunkScheduler for RarestFirstScheduler {
+    fn schedule(
+        &mut self,
+        missing: &HashSet<String>,
+        holders: &HashMap<String, Vec<String>>,
+        busy: &HashMap<String, usize>,
+        limit: usize,
+    ) -> Vec<(String, String)> {
+        let mut chunks: Vec<(&String, &Vec<String>)> = missing.iter()
+            .filter_map(|chunk| holders.get(chunk).filter(|peers| !peers.is_empty()).map(|peers| (chunk, peers)))
+            .collect();
+
+        // Embaralha antes de ordenar para desempatar chunks igualmente raros
+        chunks.shuffle(&mut rand::thread_rng());
+        chunks.sort_by_key(|(_, peers)| peers.len());
+
+        let mut load: HashMap<&str, usize> = busy.iter().map(|(peer, count)| (peer.as_str(), *count)).collect();
+        let mut assignments = vec![];
+
+        for (chunk, peers) in chunks.into_iter().take(limit) {
+            let peer = peers.iter()
+                .min_by(|a, b| {
+                    let load_a = load.get(a.as_str()).copied().unwrap_or(0);
+                    let load_b = load.get(b.as_str()).copied().unwrap_or(0);
+                    load_a.cmp(&load_b).then(self.score(b).total_cmp(&self.score(a)))
+                })
+                .expect("lista de peers não vazia");
+
+            *load.entry(peer.as_str()).or_default() += 1;
+            assignments.push((chunk.clone(), peer.clone()));
+        }
+
+        assignments
+    }
+
+    fn record_success(&mut self, peer: &str, bytes: usize, elapsed: Duration) {
+        let measured = bytes as f64 / elapsed.as_secs_f64().max(0.001);
+        self.throughput.entry(peer.to_string())
+            .and_modify(|average| *average = (1.0 - THROUGHPUT_WEIGHT) * *average + THROUGHPUT_WEIGHT * measured)
+            .or_insert(measured);
+    }
+}
@@ -0,0 +1,116 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Diretório raiz padrão; cada peer usa `p2p_data/<nome>` se nada for configurado
+pub const DEFAULT_DATA_ROOT: &str = "p2p_data";
+
+/// **Área de armazenamento de um peer**
+///
+/// - `files/`: arquivos completos (compartilhados ou já baixados) em `files/<id>/<nome>`, servidos
+///   chunk a chunk por posição; arquivos soltos em `files/` são compartilhados ao iniciar
+/// - `downloads/`: arquivos parciais (`<id>.part`) e estado dos downloads em andamento
+/// - `manifests/`: manifestos (`<id>.json`) dos arquivos compartilhados ou baixados
+#[derive(Debug, Clone)]
+pub struct Storage {
+    root: PathBuf,
+}
+
+impl Storage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Storage { root: root.into() }
+    }
+
+    /// Cria a raiz e os subdiretórios, se ainda não existirem
+    pub fn create_dirs(&self) -> io::Result<()> {
+        fs::create_dir_all(self.files_dir())?;
+        fs::create_dir_all(self.downloads_dir())?;
+        fs::create_dir_all(self.manifests_dir())
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn files_dir(&self) -> PathBuf {
+        self.root.join("files")
+    }
+
+    pub fn downloads_dir(&self) -> PathBuf {
+        self.root.join("downloads")
+    }
+
+    pub fn manifests_dir(&self) -> PathBuf {
+        self.root.join("manifests")
+    }
+
+    /// Caminho de um arquivo solto em `files/`
+    pub fn file_path(&self, file_name: &str) -> PathBuf {
+        self.files_dir().join(file_name)
+    }
+
+    /// Diretório do conteúdo de um arquivo completo, pelo ID
+    pub fn content_dir(&self, file_id: &str) -> PathBuf {
+        self.files_dir().join(file_id)
+    }
+
+    /// **Caminho de um arquivo completo**: `files/<id>/<nome>`
+    ///
+    /// Separado pelo ID, um arquivo baixado nunca substitui outro de mesmo nome.
+    pub fn content_path(&self, file_id: &str, file_name: &str) -> PathBuf {
+        self.content_dir(file_id).join(file_name)
+    }
+
+    /// Arquivo parcial de um download, com as peças gravadas nas suas posições
+    pub fn partial_path(&self, file_id: &str) -> PathBuf {
+        self.downloads_dir().join(format!("{}.part", file_id))
+    }
+
+    /// Caminho de um arquivo temporário de download
+    pub fn download_path(&self, name: &str) -> PathBuf {
+        self.downloads_dir().join(name)
+    }
+
+    /// Caminho do manifesto de um arquivo, pelo ID
+    pub fn manifest_path(&self, file_id: &str) -> PathBuf {
+        self.manifests_dir().join(format!("{}.json", file_id))
+    }
+
+    /// Lista os arquivos soltos em `files/`
+    pub fn list_files(&self) -> Vec<String> {
+        list_dir(&self.files_dir())
+    }
+
+    /// Lista os manifestos guardados
+    pub fn list_manifests(&self) -> Vec<String> {
+        list_dir(&self.manifests_dir())
+    }
+}
+
+/// **Verifica se um nome recebido pela rede é um nome de arquivo simples**
+///
+/// Rejeita caminhos (`/`, `\`), `..` e nomes vazios, evitando que um peer leia
+/// arquivos fora da área de armazenamento.
+pub fn is_safe_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.contains('/')
+        && !name.contains('\\')
+        && !name.contains('\0')
+}
+
+/// Nomes dos arquivos regulares de um diretório, em ordem alfabética
+fn list_dir(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.path().is_file())
+                .filter_map(|entry| entry.file_name().to_str().map(|s| s.to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
@@ -0,0 +1,253 @@
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::time::{Duration, Instant};
+
+/// Intervalo usado para medir a taxa atual de cada direção
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Balde de um peer sem tráfego há mais que isso é descartado (cheio, ele não guarda nada útil)
+const PEER_BUCKET_TTL: Duration = Duration::from_secs(60);
+
+/// Direções e escopos aceitos ao ajustar um limite (`limit upload 512K`, ...)
+pub const LIMIT_NAMES: &[&str] = &["upload", "download", "peer_upload", "peer_download"];
+
+/// **Limites de banda em bytes por segundo** (`0` = sem limite)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimits {
+    pub upload: u64,        // Total enviado a todos os peers
+    pub download: u64,      // Total recebido de todos os peers
+    pub peer_upload: u64,   // Enviado para cada peer
+    pub peer_download: u64, // Recebido de cada peer
+}
+
+impl RateLimits {
+    /// Altera um limite pelo nome (`upload`, `download`, `peer_upload` ou `peer_download`)
+    pub fn set(&mut self, name: &str, rate: u64) -> Result<(), String> {
+        match name.replace('-', "_").as_str() {
+            "upload" => self.upload = rate,
+            "download" => self.download = rate,
+            "peer_upload" => self.peer_upload = rate,
+            "peer_download" => self.peer_download = rate,
+            _ => return Err(format!("Limite desconhecido: '{}' (use {})", name, LIMIT_NAMES.join(", "))),
+        }
+        Ok(())
+    }
+}
+
+/// Lê uma taxa em bytes por segundo, aceitando os sufixos `K` e `M` (ex: `512K`, `2M`)
+///
+/// `0`, `off` e `unlimited` desativam o limite.
+pub fn parse_rate(value: &str) -> Result<u64, String> {
+    let upper = value.trim().to_uppercase();
+    if matches!(upper.as_str(), "OFF" | "UNLIMITED" | "NONE") {
+        return Ok(0);
+    }
+    let (digits, multiplier) = match upper.trim_end_matches("/S").trim_end_matches("IB").trim_end_matches('B') {
+        number if number.ends_with('K') => (number.trim_end_matches('K').to_string(), 1024),
+        number if number.ends_with('M') => (number.trim_end_matches('M').to_string(), 1024 * 1024),
+        number => (number.to_string(), 1),
+    };
+
+    digits.trim().parse::<u64>().ok()
+        .and_then(|rate| rate.checked_mul(multiplier))
+        .ok_or_else(|| format!("Taxa inválida: '{}' (use bytes por segundo, ex: 512K, 2M ou 0 para sem limite)", value))
+}
+
+/// Formata uma taxa para exibição
+pub fn format_rate(rate: u64) -> String {
+    format!("{:.2} KB/s", rate as f64 / 1024.0)
+}
+
+/// Formata um limite para exibição (`sem limite` quando zero)
+pub fn format_limit(limit: u64) -> String {
+    if limit == 0 {
+        "sem limite".to_string()
+    } else {
+        format_rate(limit)
+    }
+}
+
+/// Estado interno de um balde: tokens disponíveis (negativo = em dívida) e medição da taxa
+struct BucketState {
+    rate: u64,
+    tokens: f64,
+    updated: Instant,
+    window_start: Instant,
+    window_bytes: u64,
+    current_rate: f64,
+}
+
+/// **Balde de tokens**: libera `rate` bytes por segundo, com rajadas de até um segundo
+///
+/// Quem pede mais bytes do que há no balde fica devendo e espera o tempo necessário para
+/// a dívida ser paga; assim um bloco grande nunca trava o balde para sempre.
+pub struct TokenBucket {
+    state: Mutex<BucketState>,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u64) -> Self {
+        let now = Instant::now();
+        TokenBucket {
+            state: Mutex::new(BucketState {
+                rate,
+                tokens: rate as f64,
+                updated: now,
+                window_start: now,
+                window_bytes: 0,
+                current_rate: 0.0,
+            }),
+        }
+    }
+
+    /// Altera a taxa do balde em tempo de execução
+    pub fn set_rate(&self, rate: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.rate = rate;
+        state.tokens = state.tokens.min(rate as f64);
+    }
+
+    /// Reserva `bytes` e retorna quanto tempo esperar antes de usá-los
+    fn reserve(&self, bytes: usize) -> Duration {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        // 📈 Mede a taxa atual, limite ou não
+        state.window_bytes += bytes as u64;
+        let window = now.duration_since(state.window_start);
+        if window >= RATE_WINDOW {
+            state.current_rate = state.window_bytes as f64 / window.as_secs_f64();
+            state.window_start = now;
+            state.window_bytes = 0;
+        }
+
+        if state.rate == 0 {
+            state.updated = now;
+            return Duration::ZERO;
+        }
+
+        let rate = state.rate as f64;
+        let elapsed = now.duration_since(state.updated).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * rate).min(rate) - bytes as f64;
+        state.updated = now;
+
+        if state.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-state.tokens / rate)
+        }
+    }
+
+    /// Espera até que `bytes` possam passar pelo balde
+    pub async fn acquire(&self, bytes: usize) {
+        let wait = self.reserve(bytes);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Tempo desde o último uso do balde
+    fn idle_time(&self) -> Duration {
+        Instant::now().duration_since(self.state.lock().unwrap().updated)
+    }
+
+    /// Taxa medida no último intervalo, em bytes por segundo (zero se não houve tráfego recente)
+    pub fn current_rate(&self) -> u64 {
+        let state = self.state.lock().unwrap();
+        if Instant::now().duration_since(state.window_start) > RATE_WINDOW * 2 {
+            0
+        } else {
+            state.current_rate as u64
+        }
+    }
+}
+
+/// **Limites e taxas atuais, como mostrados pela API**
+#[derive(Debug, Clone, Serialize)]
+pub struct ThrottleStatus {
+    pub limits: RateLimits,
+    pub upload_rate: u64,   // Taxa de envio atual, em bytes por segundo
+    pub download_rate: u64, // Taxa de recebimento atual, em bytes por segundo
+}
+
+/// **Controle de banda do peer, nas duas direções**
+///
+/// Cada bloco enviado ou recebido passa pelo balde global da direção e pelo balde do peer
+/// do outro lado: nos downloads, o endereço (`host:porta`) de quem envia; nos uploads, o
+/// endereço anunciado por quem pede, se o seu host for o IP da conexão, ou então só esse IP.
+/// Os limites podem ser trocados a qualquer momento com `set_limits`.
+pub struct Throttle {
+    limits: Mutex<RateLimits>,
+    upload: TokenBucket,
+    download: TokenBucket,
+    peer_upload: Mutex<HashMap<String, Arc<TokenBucket>>>,
+    peer_download: Mutex<HashMap<String, Arc<TokenBucket>>>,
+}
+
+impl Throttle {
+    pub fn new(limits: RateLimits) -> Self {
+        Throttle {
+            limits: Mutex::new(limits),
+            upload: TokenBucket::new(limits.upload),
+            download: TokenBucket::new(limits.download),
+            peer_upload: Mutex::new(HashMap::new()),
+            peer_download: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn limits(&self) -> RateLimits {
+        *self.limits.lock().unwrap()
+    }
+
+    /// **Aplica novos limites**, inclusive aos baldes dos peers já conhecidos
+    pub fn set_limits(&self, limits: RateLimits) {
+        *self.limits.lock().unwrap() = limits;
+        self.upload.set_rate(limits.upload);
+        self.download.set_rate(limits.download);
+        for bucket in self.peer_upload.lock().unwrap().values() {
+            bucket.set_rate(limits.peer_upload);
+        }
+        for bucket in self.peer_download.lock().unwrap().values() {
+            bucket.set_rate(limits.peer_download);
+        }
+    }
+
+    /// Espera até poder enviar `bytes` para o peer `peer`
+    pub async fn upload(&self, peer: &str, bytes: usize) {
+        let bucket = Self::peer_bucket(&self.peer_upload, peer, self.limits().peer_upload);
+        self.upload.acquire(bytes).await;
+        bucket.acquire(bytes).await;
+    }
+
+    /// Espera até poder receber `bytes` do peer `peer`
+    pub async fn download(&self, peer: &str, bytes: usize) {
+        let bucket = Self::peer_bucket(&self.peer_download, peer, self.limits().peer_download);
+        self.download.acquire(bytes).await;
+        bucket.acquire(bytes).await;
+    }
+
+    pub fn status(&self) -> ThrottleStatus {
+        ThrottleStatus {
+            limits: self.limits(),
+            upload_rate: self.upload.current_rate(),
+            download_rate: self.download.current_rate(),
+        }
+    }
+
+    /// Balde de um peer, criado com o limite por peer na primeira vez
+    ///
+    /// Antes de criar um balde novo, descarta os ociosos que ninguém está usando, para que o
+    /// mapa não cresça com cada endereço já visto.
+    fn peer_bucket(buckets: &Mutex<HashMap<String, Arc<TokenBucket>>>, peer: &str, rate: u64) -> Arc<TokenBucket> {
+        let mut buckets = buckets.lock().unwrap();
+        if let Some(bucket) = buckets.get(peer) {
+            return bucket.clone();
+        }
+        buckets.retain(|_, bucket| Arc::strong_count(bucket) > 1 || bucket.idle_time() < PEER_BUCKET_TTL);
+        buckets.entry(peer.to_string())
+            .or_insert_with(|| Arc::new(TokenBucket::new(rate)))
+            .clone()
+    }
+}
@@ -6,6 +6,11 @@ use tower_http::cors::{CorsLayer, Any};
 use std::process::Command;
 use tokio::time::{self, Duration};
 
+use crate::config::TrackerConfig;
+use crate::federation::{Departure, Federation, Snapshot};
+use crate::manifest::{self, FileManifest};
+use crate::tracker_store::{JournalEntry, JournalStore, MemoryStore, TrackerStore};
+
 
 #[derive(Debug, Serialize, Deserialize)]
 struct PeerStartRequest {
@@ -31,21 +36,36 @@ async fn start_peer(Json(payload): Json<PeerStartRequest>) -> StatusCode {
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-struct Peer {
+pub struct Peer {
     name: String,
     address: String,
     last_seen: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-struct ChunkRegister {
+pub struct ChunkRegister {
     peer: String,
+    file_id: String,
     file_name: String,
     chunk_name: String,
     checksum: String,
     peer_address: String,
 }
 
+/// Manifesto de um arquivo publicado por um peer
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileRegister {
+    peer: String,
+    manifest: FileManifest,
+}
+
+/// Arquivo compartilhado por um peer, como aparece em `/list`
+#[derive(Debug, Serialize, Deserialize)]
+struct SharedFile {
+    id: String,
+    name: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct RegisterRequest {
     name: String,
@@ -57,16 +77,224 @@ struct RegisterRequest {
 struct PeerInfo {
     pub name: String,
     pub address: String,
-    pub files: Vec<String>,
+    pub files: Vec<SharedFile>,
 }
 
 struct TrackerState {
     peers: Mutex<HashMap<String, Peer>>,
-    chunks: Mutex<HashMap<String, Vec<ChunkRegister>>>,
+    chunks: Mutex<HashMap<String, Vec<ChunkRegister>>>, // Chunks de cada arquivo, pelo ID do conteúdo
+    files: Mutex<HashMap<String, FileRegister>>,        // Manifesto de cada arquivo, pelo ID do conteúdo
+    store: Box<dyn TrackerStore>, // Persistência das mutações (journal ou memória)
+    replica: Mutex<Replica>,         // Índices replicados dos trackers federados
+    federation: Option<Federation>,  // Trackers federados com este, se houver
+}
+
+/// Um peer identificado pelo nome e pelo endereço, para que homônimos de sites diferentes não se confundam
+type PeerKey = (String, String);
+
+/// **Índices replicados dos trackers federados**
+///
+/// Ficam separados dos índices locais e só em memória: são refeitos a cada replicação. Um peer
+/// de outro site nunca substitui um peer local, mesmo que tenha o mesmo nome.
+#[derive(Default)]
+struct Replica {
+    peers: HashMap<PeerKey, Peer>,               // Peers registrados nos trackers federados
+    chunks: HashMap<String, Vec<ChunkRegister>>, // Chunks desses peers, pelo ID do conteúdo
+    departures: HashMap<PeerKey, u64>,           // Saídas recentes (locais e replicadas), com o timestamp
+}
+
+impl Replica {
+    /// Descarta peers inativos, saídas antigas e os chunks de peers que não estão mais na réplica
+    fn prune(&mut self, now: u64) {
+        self.peers.retain(|_, peer| is_live(peer, now));
+        self.departures.retain(|_, left_at| now.saturating_sub(*left_at) < PEER_TIMEOUT_SECS);
+        for chunk_list in self.chunks.values_mut() {
+            chunk_list.retain(|chunk| self.peers.contains_key(&chunk_owner(chunk)));
+        }
+        drop_empty_files(&mut self.chunks);
+    }
 }
 
 type SharedState = Arc<TrackerState>;
 
+/// Tempo sem heartbeat (em segundos) após o qual um peer é considerado inativo
+const PEER_TIMEOUT_SECS: u64 = 180;
+
+impl TrackerState {
+    /// Grava uma mutação no backend de persistência sem interromper a requisição em caso de falha
+    fn persist(&self, entry: JournalEntry) {
+        if let Err(e) = self.store.append(&entry) {
+            println!("⚠️ Falha ao gravar no journal do tracker: {}", e);
+        }
+    }
+
+    /// **Remove peers e, em cascata, todos os chunks registrados por eles**
+    ///
+    /// Trava `peers` antes de `chunks` (e `files` por último), a mesma ordem usada em `list_peers` e
+    /// `get_file_chunks`, e mantém as travas até o fim para que nenhum leitor veja um peer removido com chunks ativos.
+    fn evict_peers<F>(&self, should_remove: F) -> Vec<String>
+    where
+        F: Fn(&Peer) -> bool,
+    {
+        let mut peers = self.peers.lock().unwrap();
+        let mut chunks = self.chunks.lock().unwrap();
+        let mut files = self.files.lock().unwrap();
+
+        let removed: Vec<String> = peers.values()
+            .filter(|peer| should_remove(peer))
+            .map(|peer| peer.name.clone())
+            .collect();
+
+        if removed.is_empty() {
+            return removed;
+        }
+
+        peers.retain(|name, _| !removed.contains(name));
+        let orphaned = purge_peer_chunks(&mut chunks, &removed);
+        forget_files(&mut files, &orphaned);
+
+        for peer in &removed {
+            self.persist(JournalEntry::UnregisterPeer { peer: peer.clone() });
+        }
+
+        removed
+    }
+
+    /// **Junta à réplica o snapshot de um tracker federado**
+    ///
+    /// O snapshot traz só os peers registrados no próprio tracker federado, então os chunks de
+    /// cada um deles substituem os que estavam na réplica. Entre registros do mesmo peer (mesmo
+    /// nome e endereço) vindos de trackers diferentes vale o `last_seen` mais recente, e uma saída
+    /// só remove o peer se for mais recente que esse `last_seen`. Peers também registrados aqui
+    /// ficam com o registro local. Chunks só entram se conferirem com o manifesto do arquivo.
+    ///
+    /// Retorna quantos peers foram removidos, quantos peers foram importados e quantos chunks a réplica ganhou.
+    fn merge(&self, snapshot: Snapshot) -> (usize, usize, usize) {
+        let now = current_timestamp();
+        let peers = self.peers.lock().unwrap();
+        let mut files = self.files.lock().unwrap();
+        let mut replica = self.replica.lock().unwrap();
+
+        // 🔹 Saídas: primeiro, para que um peer que saiu não seja importado de volta
+        let mut departed = 0;
+        for departure in snapshot.departures {
+            if now.saturating_sub(departure.left_at) >= PEER_TIMEOUT_SECS {
+                continue;
+            }
+            let key = (departure.peer, departure.address);
+            if replica.peers.get(&key).is_some_and(|peer| peer.last_seen <= departure.left_at) {
+                replica.peers.remove(&key);
+                departed += 1;
+            }
+            let left_at = replica.departures.entry(key).or_insert(departure.left_at);
+            *left_at = (*left_at).max(departure.left_at);
+        }
+
+        let mut imported_peers = 0;
+        let mut owners: Vec<PeerKey> = vec![];
+        for peer in snapshot.peers {
+            let key = (peer.name.clone(), peer.address.clone());
+            let is_local = peers.get(&peer.name).is_some_and(|local| local.address == peer.address);
+            let has_left = replica.departures.get(&key).is_some_and(|left_at| *left_at >= peer.last_seen);
+            if !is_live(&peer, now) || is_local || has_left {
+                continue;
+            }
+            match replica.peers.get_mut(&key) {
+                Some(known) => known.last_seen = known.last_seen.max(peer.last_seen),
+                None => {
+                    imported_peers += 1;
+                    replica.peers.insert(key.clone(), peer);
+                }
+            }
+            owners.push(key);
+        }
+
+        for file in snapshot.files {
+            if !files.contains_key(&file.manifest.id) && file.manifest.verify().is_ok() {
+                files.insert(file.manifest.id.clone(), file);
+            }
+        }
+
+        // 🔹 Chunks: o snapshot tem a lista completa de cada peer que veio nele
+        let before: usize = replica.chunks.values().map(Vec::len).sum();
+        for chunk_list in replica.chunks.values_mut() {
+            chunk_list.retain(|chunk| !owners.contains(&chunk_owner(chunk)));
+        }
+        for chunk in snapshot.chunks {
+            if !owners.contains(&chunk_owner(&chunk)) || !chunk_matches_manifest(&files, &chunk) {
+                continue;
+            }
+            let list = replica.chunks.entry(chunk.file_id.clone()).or_default();
+            if !list.iter().any(|c| c.chunk_name == chunk.chunk_name && chunk_owner(c) == chunk_owner(&chunk)) {
+                list.push(chunk);
+            }
+        }
+        replica.prune(now);
+        let imported_chunks = replica.chunks.values().map(Vec::len).sum::<usize>().saturating_sub(before);
+
+        (departed, imported_peers, imported_chunks)
+    }
+}
+
+/// Remove do índice todos os chunks dos peers informados, descartando arquivos sem nenhum dono
+///
+/// Retorna os nomes dos arquivos descartados.
+fn purge_peer_chunks(chunks: &mut HashMap<String, Vec<ChunkRegister>>, peer_names: &[String]) -> Vec<String> {
+    for chunk_list in chunks.values_mut() {
+        chunk_list.retain(|chunk| !peer_names.contains(&chunk.peer));
+    }
+    drop_empty_files(chunks)
+}
+
+/// Remove um único chunk de um peer, descartando arquivos que ficarem sem nenhum dono
+///
+/// Retorna os nomes dos arquivos descartados.
+fn remove_peer_chunk(chunks: &mut HashMap<String, Vec<ChunkRegister>>, peer_name: &str, chunk_name: &str) -> Vec<String> {
+    for chunk_list in chunks.values_mut() {
+        chunk_list.retain(|chunk| !(chunk.peer == peer_name && chunk.chunk_name == chunk_name));
+    }
+    drop_empty_files(chunks)
+}
+
+/// Descarta os arquivos sem nenhum chunk registrado e retorna seus IDs
+fn drop_empty_files(chunks: &mut HashMap<String, Vec<ChunkRegister>>) -> Vec<String> {
+    let empty: Vec<String> = chunks.iter()
+        .filter(|(_, chunk_list)| chunk_list.is_empty())
+        .map(|(file_name, _)| file_name.clone())
+        .collect();
+    for file_name in &empty {
+        chunks.remove(file_name);
+    }
+    empty
+}
+
+/// Esquece os manifestos de arquivos que não têm mais nenhum chunk na rede
+fn forget_files(files: &mut HashMap<String, FileRegister>, file_ids: &[String]) {
+    for file_id in file_ids {
+        files.remove(file_id);
+    }
+}
+
+/// Peer dono de um chunk, pelo nome e pelo endereço
+fn chunk_owner(chunk: &ChunkRegister) -> PeerKey {
+    (chunk.peer.clone(), chunk.peer_address.clone())
+}
+
+/// Verifica se o chunk pertence à árvore de um arquivo publicado, com o mesmo hash
+fn chunk_matches_manifest(files: &HashMap<String, FileRegister>, chunk: &ChunkRegister) -> bool {
+    let expected_checksum = files.get(&chunk.file_id)
+        .and_then(|file| {
+            let (file_id, index) = manifest::parse_chunk_name(&chunk.chunk_name)?;
+            (file_id == chunk.file_id).then(|| file.manifest.chunk_hashes.get(index).cloned())?
+        });
+    expected_checksum.as_deref() == Some(chunk.checksum.as_str())
+}
+
+/// Verifica se o peer enviou sinal de vida dentro do limite de inatividade
+fn is_live(peer: &Peer, now: u64) -> bool {
+    now.saturating_sub(peer.last_seen) < PEER_TIMEOUT_SECS
+}
+
 /// **Registra um Peer no Tracker**
 async fn register_peer(
     State(state): State<SharedState>,
@@ -78,11 +306,14 @@ async fn register_peer(
         return (StatusCode::BAD_REQUEST, Json("Nome já registrado".to_string()));
     }
 
-    peers.insert(payload.name.clone(), Peer {
+    let peer = Peer {
         name: payload.name.clone(),
         address: payload.address.clone(),
         last_seen: current_timestamp(),
-    });
+    };
+    peers.insert(payload.name.clone(), peer.clone());
+    state.replica.lock().unwrap().departures.remove(&(payload.name.clone(), payload.address.clone()));
+    state.persist(JournalEntry::RegisterPeer { peer });
 
     println!("✅ Peer registrado: {:?}", payload);
     (StatusCode::OK, Json(format!("{} registrado com sucesso!", payload.name)))
@@ -93,12 +324,19 @@ async fn register_chunks(
     State(state): State<SharedState>,
     Json(payload): Json<ChunkRegister>,
 ) -> (StatusCode, Json<String>) {
+    // 🌳 O chunk precisa pertencer à árvore de um arquivo publicado, com o mesmo hash
+    if !chunk_matches_manifest(&state.files.lock().unwrap(), &payload) {
+        println!("🚫 Chunk '{}' recusado: não confere com o manifesto do arquivo.", payload.chunk_name);
+        return (StatusCode::BAD_REQUEST, Json("Chunk não confere com o manifesto do arquivo".to_string()));
+    }
+
     let mut chunks = state.chunks.lock().unwrap();
-    let entry = chunks.entry(payload.file_name.clone()).or_insert(vec![]);
+    let entry = chunks.entry(payload.file_id.clone()).or_insert(vec![]);
     
     // **Evita registrar duplicatas**
     if !entry.iter().any(|c| c.chunk_name == payload.chunk_name && c.peer == payload.peer) {
-        entry.push(payload);
+        entry.push(payload.clone());
+        state.persist(JournalEntry::RegisterChunk { chunk: payload });
         println!("📦 Chunk registrado no Tracker!");
         (StatusCode::OK, Json("Chunk registrado com sucesso!".to_string()))
     } else {
@@ -107,43 +345,164 @@ async fn register_chunks(
     }
 }
 
+/// **Publica o manifesto de um arquivo**
+///
+/// O índice é pelo ID do conteúdo, então arquivos homônimos com conteúdos diferentes
+/// convivem; manifestos cujo ID não corresponde aos hashes são recusados.
+async fn register_file(
+    State(state): State<SharedState>,
+    Json(payload): Json<FileRegister>,
+) -> (StatusCode, Json<String>) {
+    if let Err(e) = payload.manifest.verify() {
+        return (StatusCode::BAD_REQUEST, Json(format!("Manifesto inválido: {}", e)));
+    }
+
+    let mut files = state.files.lock().unwrap();
+    if files.contains_key(&payload.manifest.id) {
+        return (StatusCode::OK, Json("Arquivo já registrado, ignorando.".to_string()));
+    }
+
+    let manifest = &payload.manifest;
+    println!("🗂️ Arquivo '{}' registrado: {} bytes, {} chunk(s), ID {}", manifest.file_name, manifest.size, manifest.chunk_count(), manifest.id);
+    files.insert(manifest.id.clone(), payload.clone());
+    state.persist(JournalEntry::RegisterFile { file: payload });
+    (StatusCode::OK, Json("Arquivo registrado com sucesso!".to_string()))
+}
+
+/// **Repassa uma consulta sem resposta local aos trackers federados**
+///
+/// Consultas que já vieram de um tracker federado (`local=1`) não são repassadas de novo.
+async fn ask_federation<T: serde::de::DeserializeOwned>(
+    state: &TrackerState,
+    params: &HashMap<String, String>,
+    path: &str,
+    query: &[(&str, &str)],
+) -> Vec<T> {
+    match &state.federation {
+        Some(federation) if !params.contains_key("local") => federation.query(path, query).await,
+        _ => vec![],
+    }
+}
+
+/// **Obtém o manifesto de um arquivo pelo ID** (`null` se desconhecido)
+///
+/// Se o arquivo não é conhecido aqui, pergunta aos trackers federados.
+async fn get_file_info(
+    State(state): State<SharedState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Json<Option<FileManifest>> {
+    let file_id = params.get("id").cloned().unwrap_or_default();
+    let local = state.files.lock().unwrap().get(&file_id).map(|file| file.manifest.clone());
+    if local.is_some() {
+        return Json(local);
+    }
+
+    let answers: Vec<Option<FileManifest>> = ask_federation(&state, &params, "/get_file_info", &[("id", &file_id)]).await;
+    Json(answers.into_iter().flatten().find(|manifest| manifest.id == file_id && manifest.verify().is_ok()))
+}
+
+/// **Procura arquivos pelo nome**, retornando os manifestos de todos os conteúdos com esse nome
+async fn find_files(
+    State(state): State<SharedState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Json<Vec<FileManifest>> {
+    let file_name = params.get("name").cloned().unwrap_or_default();
+    let mut result: Vec<FileManifest> = state.files.lock().unwrap().values()
+        .filter(|file| file.manifest.file_name == file_name)
+        .map(|file| file.manifest.clone())
+        .collect();
+
+    if result.is_empty() {
+        let answers: Vec<Vec<FileManifest>> = ask_federation(&state, &params, "/find_files", &[("name", &file_name)]).await;
+        for manifest in answers.into_iter().flatten() {
+            if manifest.file_name == file_name && manifest.verify().is_ok() && !result.iter().any(|known| known.id == manifest.id) {
+                result.push(manifest);
+            }
+        }
+    }
+
+    Json(result)
+}
+
 /// **Obtém a lista de chunks disponíveis no Tracker**
+///
+/// Retorna apenas chunks de peers ainda ativos, para que o download não perca tempo com endereços mortos,
+/// incluindo os peers replicados dos trackers federados. Se ninguém conhecido aqui tem o arquivo,
+/// pergunta aos trackers federados, que podem conhecer peers ainda não replicados.
 async fn get_file_chunks(
     State(state): State<SharedState>,
     Query(params): Query<HashMap<String, String>>,
 ) -> Json<Vec<ChunkRegister>> {
+    let file_id = params.get("id").cloned().unwrap_or_default();
+    let mut result: Vec<ChunkRegister> = {
+        let peers = state.peers.lock().unwrap();
+        let chunks = state.chunks.lock().unwrap();
+        let replica = state.replica.lock().unwrap();
+        let now = current_timestamp();
+
+        let local = chunks.get(&file_id).into_iter().flatten()
+            .filter(|chunk| peers.get(&chunk.peer).is_some_and(|peer| is_live(peer, now)));
+        let replicated = replica.chunks.get(&file_id).into_iter().flatten()
+            .filter(|chunk| replica.peers.get(&chunk_owner(chunk)).is_some_and(|peer| is_live(peer, now)));
+        local.chain(replicated).cloned().collect()
+    };
+
+    if result.is_empty() {
+        let answers: Vec<Vec<ChunkRegister>> = ask_federation(&state, &params, "/get_file_chunks", &[("id", &file_id)]).await;
+        for chunk in answers.into_iter().flatten() {
+            if chunk.file_id == file_id && !result.iter().any(|c| c.chunk_name == chunk.chunk_name && chunk_owner(c) == chunk_owner(&chunk)) {
+                result.push(chunk);
+            }
+        }
+    }
+    println!("📄 Chunks encontrados para '{}': {:?}", file_id, result);
+
+    Json(result)
+}
+
+/// **Lista os nomes dos chunks que o Tracker associa a um peer**
+async fn get_peer_chunks(
+    State(state): State<SharedState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Json<Vec<String>> {
     let chunks = state.chunks.lock().unwrap();
-    let file_name = params.get("file").cloned().unwrap_or_default();
-    
-    let result = chunks.get(&file_name).cloned().unwrap_or(vec![]);
-    println!("📄 Chunks encontrados para '{}': {:?}", file_name, result);
+    let peer_name = params.get("peer").cloned().unwrap_or_default();
+
+    let result: Vec<String> = chunks.values()
+        .flatten()
+        .filter(|chunk| chunk.peer == peer_name)
+        .map(|chunk| chunk.chunk_name.clone())
+        .collect();
 
     Json(result)
 }
 
 /// **Lista todos os peers e arquivos disponíveis**
+///
+/// Os peers locais vêm primeiro, seguidos dos replicados dos trackers federados.
 async fn list_peers(
     State(state): State<SharedState>,
 ) -> Json<Vec<PeerInfo>> {
     let peers = state.peers.lock().unwrap();
     let chunks = state.chunks.lock().unwrap();
+    let replica = state.replica.lock().unwrap();
     
     let mut infos: Vec<PeerInfo> = Vec::new();
     
     // Para cada peer registrado, coletamos os arquivos (caso existam) a partir dos chunks
     for (peer_name, peer) in peers.iter() {
-        let mut files_set: std::collections::HashSet<String> = std::collections::HashSet::new();
+        let mut files_map: HashMap<String, String> = HashMap::new();
         
         // Itera por todos os chunks e seleciona os arquivos deste peer
         for (_, chunk_list) in chunks.iter() {
             for chunk in chunk_list {
                 if &chunk.peer == peer_name {
-                    files_set.insert(chunk.file_name.clone());
+                    files_map.insert(chunk.file_id.clone(), chunk.file_name.clone());
                 }
             }
         }
         
-        let files: Vec<String> = files_set.into_iter().collect();
+        let files: Vec<SharedFile> = files_map.into_iter().map(|(id, name)| SharedFile { id, name }).collect();
         
         infos.push(PeerInfo {
             name: peer_name.clone(),
@@ -151,6 +510,19 @@ async fn list_peers(
             files,
         });
     }
+
+    let now = current_timestamp();
+    for (key, peer) in replica.peers.iter().filter(|(_, peer)| is_live(peer, now)) {
+        let mut files_map: HashMap<String, String> = HashMap::new();
+        for chunk in replica.chunks.values().flatten().filter(|chunk| chunk_owner(chunk) == *key) {
+            files_map.insert(chunk.file_id.clone(), chunk.file_name.clone());
+        }
+        infos.push(PeerInfo {
+            name: peer.name.clone(),
+            address: peer.address.clone(),
+            files: files_map.into_iter().map(|(id, name)| SharedFile { id, name }).collect(),
+        });
+    }
     
     println!("📋 Lista de Peers e Arquivos: {:?}", infos);
     Json(infos)
@@ -163,23 +535,27 @@ async fn unregister_file(
     Json(payload): Json<HashMap<String, String>>,
 ) -> (StatusCode, Json<String>) {
     let peer_name = payload.get("peer").cloned().unwrap_or_default();
-    let file_name = payload.get("file").cloned().unwrap_or_default();
+    let file_id = payload.get("id").cloned().unwrap_or_default();
     let mut chunks = state.chunks.lock().unwrap();
 
-    if let Some(entries) = chunks.get_mut(&file_name) {
+    if let Some(entries) = chunks.get_mut(&file_id) {
         entries.retain(|chunk| chunk.peer != peer_name);
         if entries.is_empty() {
-            chunks.remove(&file_name);
+            chunks.remove(&file_id);
+            forget_files(&mut state.files.lock().unwrap(), std::slice::from_ref(&file_id));
         }
-        println!("🚨 Peer '{}' removeu o arquivo '{}'", peer_name, file_name);
-        return (StatusCode::OK, Json(format!("Arquivo '{}' removido para peer '{}'", file_name, peer_name)));
+        state.persist(JournalEntry::UnregisterFile { peer: peer_name.clone(), file: file_id.clone() });
+        println!("🚨 Peer '{}' removeu o arquivo '{}'", peer_name, file_id);
+        return (StatusCode::OK, Json(format!("Arquivo '{}' removido para peer '{}'", file_id, peer_name)));
     }
 
-    (StatusCode::NOT_FOUND, Json(format!("Arquivo '{}' não encontrado.", file_name)))
+    (StatusCode::NOT_FOUND, Json(format!("Arquivo '{}' não encontrado.", file_id)))
 }
 
 /// **Recebe heartbeat dos peers ativos**
-/// **Recebe heartbeat dos peers ativos**
+///
+/// Heartbeats não vão para o journal: após um restart, o `last_seen` gravado no registro
+/// faz o peer expirar no próximo `cleanup_peers` se ele não voltar a dar sinal.
 async fn heartbeat(
     State(state): State<SharedState>,
     Json(peer_name): Json<String>, // Recebe apenas o nome do peer
@@ -199,19 +575,14 @@ async fn heartbeat(
 
 async fn cleanup_peers(state: SharedState) {
     loop {
-        time::sleep(Duration::from_secs(180)).await; // Executa a cada 3 minutos
-        let mut peers = state.peers.lock().unwrap();
+        time::sleep(Duration::from_secs(PEER_TIMEOUT_SECS)).await; // Executa a cada 3 minutos
         let now = current_timestamp();
 
-        let removed_peers: Vec<String> = peers.iter()
-            .filter(|(_, peer)| now - peer.last_seen >= 180)
-            .map(|(name, _)| name.clone())
-            .collect();
-
-        peers.retain(|_, peer| now - peer.last_seen < 180);
+        let removed_peers = state.evict_peers(|peer| !is_live(peer, now));
+        state.replica.lock().unwrap().prune(now);
 
         if !removed_peers.is_empty() {
-            println!("🧹 Removendo peers inativos: {:?}", removed_peers);
+            println!("🧹 Removendo peers inativos e seus chunks: {:?}", removed_peers);
         }
     }
 }
@@ -230,10 +601,11 @@ async fn unregister_peer(
     Json(payload): Json<HashMap<String, String>>,
 ) -> (StatusCode, Json<String>) {
     let peer_name = payload.get("peer").cloned().unwrap_or_default();
-    let mut peers = state.peers.lock().unwrap();
 
-    if peers.remove(&peer_name).is_some() {
-        println!("🚨 Peer '{}' saiu da rede e foi removido.", peer_name);
+    let address = state.peers.lock().unwrap().get(&peer_name).map(|peer| peer.address.clone());
+    if let (Some(address), false) = (address, state.evict_peers(|peer| peer.name == peer_name).is_empty()) {
+        state.replica.lock().unwrap().departures.insert((peer_name.clone(), address), current_timestamp());
+        println!("🚨 Peer '{}' saiu da rede e foi removido junto com seus chunks.", peer_name);
         return (StatusCode::OK, Json(format!("Peer '{}' removido.", peer_name)));
     }
 
@@ -241,7 +613,6 @@ async fn unregister_peer(
 }
 
 /// **Remove um Chunk específico de um Peer**
-#[allow(dead_code)] 
 async fn unregister_chunk(
     State(state): State<SharedState>,
     Json(payload): Json<HashMap<String, String>>,
@@ -250,47 +621,389 @@ async fn unregister_chunk(
     let chunk_name = payload.get("chunk").cloned().unwrap_or_default();
     let mut chunks = state.chunks.lock().unwrap();
 
-    for (_, chunk_list) in chunks.iter_mut() {
-        chunk_list.retain(|chunk| !(chunk.peer == peer_name && chunk.chunk_name == chunk_name));
-    }
+    let orphaned = remove_peer_chunk(&mut chunks, &peer_name, &chunk_name);
+    forget_files(&mut state.files.lock().unwrap(), &orphaned);
+    state.persist(JournalEntry::UnregisterChunk { peer: peer_name.clone(), chunk: chunk_name.clone() });
 
     println!("🚨 Peer '{}' removeu o chunk '{}'", peer_name, chunk_name);
     (StatusCode::OK, Json(format!("Chunk '{}' removido para peer '{}'", chunk_name, peer_name)))
 }
 
 
-/// **Inicia o Tracker**
-pub async fn start_tracker() {
-    let state = Arc::new(TrackerState {
+/// **Entrega os índices deste tracker a um tracker federado**
+///
+/// Só vão os peers ativos registrados aqui, com seus chunks e manifestos, e não o que foi
+/// replicado de outros trackers: cada tracker é a fonte dos próprios peers. As saídas vão todas,
+/// para que um peer que saiu não volte por um tracker que ainda não soube da saída.
+async fn federation_snapshot(
+    State(state): State<SharedState>,
+) -> Json<Snapshot> {
+    let now = current_timestamp();
+    let peers = state.peers.lock().unwrap();
+    let chunks = state.chunks.lock().unwrap();
+    let files = state.files.lock().unwrap();
+    let replica = state.replica.lock().unwrap();
+
+    Json(Snapshot {
+        peers: peers.values().filter(|peer| is_live(peer, now)).cloned().collect(),
+        files: files.values().filter(|file| chunks.contains_key(&file.manifest.id)).cloned().collect(),
+        chunks: chunks.values().flatten().cloned().collect(),
+        departures: replica.departures.iter()
+            .map(|((peer, address), left_at)| Departure { peer: peer.clone(), address: address.clone(), left_at: *left_at })
+            .collect(),
+    })
+}
+
+/// **Replica periodicamente os índices dos trackers federados**
+async fn federate(state: SharedState) {
+    let Some(federation) = &state.federation else {
+        return;
+    };
+    println!("🤝 Tracker federado com: {}", federation.trackers().join(", "));
+
+    let mut interval = time::interval(federation.interval);
+    loop {
+        interval.tick().await;
+        replicate(&state, federation).await;
+    }
+}
+
+/// Puxa o snapshot de cada tracker federado e o junta à réplica, uma vez
+async fn replicate(state: &TrackerState, federation: &Federation) {
+    let pulls = futures::future::join_all(
+        federation.trackers().iter().map(|tracker_url| async move { (tracker_url, federation.pull(tracker_url).await) })
+    ).await;
+
+    for (tracker_url, pulled) in pulls {
+        match pulled {
+            Ok(snapshot) => {
+                let (departed, peers, chunks) = state.merge(snapshot);
+                if departed + peers + chunks > 0 {
+                    println!("🤝 Replicado de {}: {} peer(s), {} chunk(s), {} saída(s)", tracker_url, peers, chunks, departed);
+                }
+            }
+            Err(e) => println!("⚠️ Tracker federado {} inacessível: {}", tracker_url, e),
+        }
+    }
+}
+
+/// **Reconstrói o estado do tracker a partir das entradas persistidas**
+fn replay(state: &TrackerState, entries: Vec<JournalEntry>) {
+    let mut peers = state.peers.lock().unwrap();
+    let mut chunks = state.chunks.lock().unwrap();
+    let mut files = state.files.lock().unwrap();
+
+    for entry in entries {
+        match entry {
+            JournalEntry::RegisterPeer { peer } => {
+                peers.insert(peer.name.clone(), peer);
+            }
+            JournalEntry::RegisterChunk { chunk } => {
+                let list = chunks.entry(chunk.file_id.clone()).or_default();
+                if !list.iter().any(|c| c.chunk_name == chunk.chunk_name && c.peer == chunk.peer) {
+                    list.push(chunk);
+                }
+            }
+            JournalEntry::RegisterFile { file } => {
+                files.insert(file.manifest.id.clone(), file);
+            }
+            JournalEntry::UnregisterFile { peer, file } => {
+                if let Some(list) = chunks.get_mut(&file) {
+                    list.retain(|chunk| chunk.peer != peer);
+                    if list.is_empty() {
+                        chunks.remove(&file);
+                        forget_files(&mut files, &[file]);
+                    }
+                }
+            }
+            JournalEntry::UnregisterChunk { peer, chunk } => {
+                let orphaned = remove_peer_chunk(&mut chunks, &peer, &chunk);
+                forget_files(&mut files, &orphaned);
+            }
+            JournalEntry::UnregisterPeer { peer } => {
+                peers.remove(&peer);
+                let orphaned = purge_peer_chunks(&mut chunks, &[peer]);
+                forget_files(&mut files, &orphaned);
+            }
+        }
+    }
+}
+
+/// **Gera um snapshot do estado atual no formato do journal**
+fn snapshot(state: &TrackerState) -> Vec<JournalEntry> {
+    let peers = state.peers.lock().unwrap();
+    let chunks = state.chunks.lock().unwrap();
+    let files = state.files.lock().unwrap();
+
+    peers.values()
+        .cloned()
+        .map(|peer| JournalEntry::RegisterPeer { peer })
+        .chain(files.values().cloned().map(|file| JournalEntry::RegisterFile { file }))
+        .chain(chunks.values().flatten().cloned().map(|chunk| JournalEntry::RegisterChunk { chunk }))
+        .collect()
+}
+
+/// Cria o backend de persistência escolhido na configuração
+fn open_store(config: &TrackerConfig) -> Box<dyn TrackerStore> {
+    if config.store == "memory" {
+        return Box::new(MemoryStore);
+    }
+
+    match JournalStore::open(&config.journal_path) {
+        Ok(store) => Box::new(store),
+        Err(e) => {
+            println!("❌ Não foi possível abrir o journal '{}': {}. Usando apenas memória.", config.journal_path, e);
+            Box::new(MemoryStore)
+        }
+    }
+}
+
+/// Monta o estado vazio do tracker a partir da configuração
+fn build_state(config: &TrackerConfig) -> SharedState {
+    Arc::new(TrackerState {
         peers: Mutex::new(HashMap::new()),
         chunks: Mutex::new(HashMap::new()),
-    });
-
-    // 🔹 Inicia a limpeza automática de peers inativos
-    tokio::spawn(cleanup_peers(state.clone()));
+        files: Mutex::new(HashMap::new()),
+        store: open_store(config),
+        replica: Mutex::new(Replica::default()),
+        federation: (!config.federation.is_empty())
+            .then(|| Federation::new(config.federation.clone(), Duration::from_secs(config.federation_interval))),
+    })
+}
 
-    let app = Router::new()
+/// **Rotas HTTP do tracker**
+fn routes() -> Router<SharedState> {
+    Router::new()
         .route("/start_peer", post(start_peer)) 
         .route("/register", post(register_peer))
         .route("/heartbeat", post(heartbeat)) 
         .route("/register_chunk", post(register_chunks))
+        .route("/register_file", post(register_file))
+        .route("/get_file_info", get(get_file_info))
+        .route("/find_files", get(find_files))
         .route("/get_file_chunks", get(get_file_chunks))
+        .route("/get_peer_chunks", get(get_peer_chunks))
+        .route("/unregister_chunk", post(unregister_chunk))
         .route("/list", get(list_peers))
         .route("/unregister_file", post(unregister_file))
         .route("/unregister_peer", post(unregister_peer))
+        .route("/federation/snapshot", get(federation_snapshot))
         .layer(
             CorsLayer::new()
                 .allow_origin(Any) 
                 .allow_methods(Any)  
                 .allow_headers(Any),
         )
-        .with_state(state.clone());
+}
+
+/// **Inicia o Tracker**
+pub async fn start_tracker(config: TrackerConfig) -> Result<(), String> {
+    // Escuta antes de restaurar o estado, para não mexer no journal de um tracker que já usa a porta
+    let listener = TcpListener::bind(config.bind_address()).await
+        .map_err(|e| format!("Não foi possível escutar em {}: {}", config.bind_address(), e))?;
+
+    let state = build_state(&config);
+
+    // 🔹 Restaura o estado salvo e compacta o journal
+    match state.store.load() {
+        Ok(entries) => {
+            let count = entries.len();
+            replay(&state, entries);
+            if let Err(e) = state.store.compact(&snapshot(&state)) {
+                println!("⚠️ Falha ao compactar o journal: {}", e);
+            }
+            println!(
+                "💾 Estado restaurado: {} entrada(s), {} peer(s), {} arquivo(s)",
+                count,
+                state.peers.lock().unwrap().len(),
+                state.chunks.lock().unwrap().len()
+            );
+        }
+        Err(e) => println!("⚠️ Falha ao ler o estado salvo do tracker: {}", e),
+    }
 
-    let listener = TcpListener::bind("0.0.0.0:9500").await.unwrap();
-    println!("📡 Tracker rodando na porta 9500...");
-    axum::serve(listener, app).await.unwrap();
+    // 🔹 Inicia a limpeza automática de peers inativos
+    tokio::spawn(cleanup_peers(state.clone()));
+
+    // 🔹 Replica os índices dos trackers federados, se houver
+    tokio::spawn(federate(state.clone()));
+
+    let app = routes().with_state(state.clone());
+
+    println!("📡 Tracker rodando na porta {}...", config.port);
+    axum::serve(listener, app).await.map_err(|e| format!("Servidor do tracker parou: {}", e))
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::ChunkingMode;
+
+    fn state() -> TrackerState {
+        TrackerState {
+            peers: Mutex::new(HashMap::new()),
+            chunks: Mutex::new(HashMap::new()),
+            files: Mutex::new(HashMap::new()),
+            store: Box::new(MemoryStore),
+            replica: Mutex::new(Replica::default()),
+            federation: None,
+        }
+    }
+
+    fn file() -> FileManifest {
+        let hashes = vec!["a".repeat(64), "b".repeat(64)];
+        FileManifest::new("a.bin", 8, 4, ChunkingMode::Fixed, vec![], hashes, "c".repeat(64))
+    }
+
+    fn register_peer(name: &str) -> JournalEntry {
+        JournalEntry::RegisterPeer { peer: Peer { name: name.to_string(), address: format!("{}:8000", name), last_seen: 1 } }
+    }
+
+    fn register_chunk(peer: &str, manifest: &FileManifest, index: usize) -> JournalEntry {
+        JournalEntry::RegisterChunk {
+            chunk: ChunkRegister {
+                peer: peer.to_string(),
+                file_id: manifest.id.clone(),
+                file_name: manifest.file_name.clone(),
+                chunk_name: manifest.chunk_name(index),
+                checksum: manifest.chunk_hashes[index].clone(),
+                peer_address: format!("{}:8000", peer),
+            },
+        }
+    }
+
+    /// Journal de dois peers com o mesmo arquivo (o chunk 0 de alice aparece duas vezes)
+    fn journal(manifest: &FileManifest) -> Vec<JournalEntry> {
+        vec![
+            register_peer("alice"),
+            register_peer("bob"),
+            JournalEntry::RegisterFile { file: FileRegister { peer: "alice".to_string(), manifest: manifest.clone() } },
+            register_chunk("alice", manifest, 0),
+            register_chunk("alice", manifest, 1),
+            register_chunk("alice", manifest, 0),
+            register_chunk("bob", manifest, 1),
+        ]
+    }
+
+    #[test]
+    fn replay_rebuilds_the_indexes() {
+        let manifest = file();
+        let state = state();
+        replay(&state, journal(&manifest));
+
+        assert_eq!(state.peers.lock().unwrap().len(), 2);
+        assert_eq!(state.chunks.lock().unwrap()[&manifest.id].len(), 3);
+        assert!(state.files.lock().unwrap().contains_key(&manifest.id));
+    }
+
+    #[test]
+    fn replay_forgets_files_without_owners() {
+        let manifest = file();
+        let state = state();
+        let mut entries = journal(&manifest);
+        entries.push(JournalEntry::UnregisterChunk { peer: "bob".to_string(), chunk: manifest.chunk_name(1) });
+        entries.push(JournalEntry::UnregisterPeer { peer: "alice".to_string() });
+        replay(&state, entries);
+
+        assert_eq!(state.peers.lock().unwrap().keys().collect::<Vec<_>>(), ["bob"]);
+        assert!(state.chunks.lock().unwrap().is_empty());
+        assert!(state.files.lock().unwrap().is_empty());
+    }
 
+    #[test]
+    fn replay_keeps_files_with_other_owners() {
+        let manifest = file();
+        let state = state();
+        let mut entries = journal(&manifest);
+        entries.push(JournalEntry::UnregisterFile { peer: "alice".to_string(), file: manifest.id.clone() });
+        replay(&state, entries);
+        let chunks = state.chunks.lock().unwrap();
+        assert!(chunks[&manifest.id].iter().all(|chunk| chunk.peer == "bob"));
+        assert!(state.files.lock().unwrap().contains_key(&manifest.id));
+    }
 
+    #[test]
+    fn snapshot_replays_to_the_same_state() {
+        let manifest = file();
+        let original = state();
+        replay(&original, journal(&manifest));
+
+        let restored = state();
+        replay(&restored, snapshot(&original));
+        let mut peers: Vec<String> = restored.peers.lock().unwrap().keys().cloned().collect();
+        peers.sort();
+        assert_eq!(peers, ["alice", "bob"]);
+        assert_eq!(restored.chunks.lock().unwrap()[&manifest.id].len(), 3);
+        assert_eq!(restored.files.lock().unwrap()[&manifest.id].manifest, manifest);
+    }
 
+    /// Sobe um tracker em memória em 127.0.0.1, federado com `federation`
+    async fn spawn_tracker(listener: TcpListener, federation: Vec<String>) -> SharedState {
+        let config = TrackerConfig { store: "memory".to_string(), federation, ..Default::default() };
+        let state = build_state(&config);
+        let app = routes().with_state(state.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        state
+    }
+
+    /// Registra um peer com todos os chunks de `manifest` num tracker
+    async fn publish(tracker_url: &str, name: &str, address: &str, manifest: &FileManifest) {
+        let client = reqwest::Client::new();
+        let post = |path: &str, body: serde_json::Value| client.post(format!("{}{}", tracker_url, path)).json(&body).send();
+        let status = post("/register", serde_json::json!({ "name": name, "address": address })).await.unwrap().status();
+        assert!(status.is_success());
+        let file = FileRegister { peer: name.to_string(), manifest: manifest.clone() };
+        post("/register_file", serde_json::to_value(file).unwrap()).await.unwrap();
+        for index in 0..manifest.chunk_count() {
+            let chunk = ChunkRegister {
+                peer: name.to_string(),
+                file_id: manifest.id.clone(),
+                file_name: manifest.file_name.clone(),
+                chunk_name: manifest.chunk_name(index),
+                checksum: manifest.chunk_hashes[index].clone(),
+                peer_address: address.to_string(),
+            };
+            post("/register_chunk", serde_json::to_value(chunk).unwrap()).await.unwrap();
+        }
+    }
+
+    async fn fetch<T: serde::de::DeserializeOwned>(url: String) -> T {
+        reqwest::get(url).await.unwrap().json().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn federated_trackers_share_peers_and_keep_homonyms_apart() {
+        let first = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let second = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let first_url = format!("http://{}", first.local_addr().unwrap());
+        let second_url = format!("http://{}", second.local_addr().unwrap());
+        let first_state = spawn_tracker(first, vec![second_url.clone()]).await;
+        let second_state = spawn_tracker(second, vec![first_url.clone()]).await;
+
+        let manifest = file();
+        publish(&first_url, "alice", "127.0.0.1:9001", &manifest).await;
+
+        // Antes da replicação, a consulta sem resposta local é repassada ao outro tracker
+        let chunks: Vec<ChunkRegister> = fetch(format!("{}/get_file_chunks?id={}", second_url, manifest.id)).await;
+        assert_eq!(chunks.len(), 2);
+        let chunks: Vec<ChunkRegister> = fetch(format!("{}/get_file_chunks?id={}&local=1", second_url, manifest.id)).await;
+        assert!(chunks.is_empty());
+
+        // Depois dela, o segundo tracker conhece alice e os seus chunks
+        replicate(&second_state, second_state.federation.as_ref().unwrap()).await;
+        let chunks: Vec<ChunkRegister> = fetch(format!("{}/get_file_chunks?id={}&local=1", second_url, manifest.id)).await;
+        assert_eq!(chunks.len(), 2);
+        let peers: Vec<PeerInfo> = fetch(format!("{}/list", second_url)).await;
+        assert!(peers.iter().any(|peer| peer.name == "alice" && peer.files.iter().any(|file| file.id == manifest.id)));
+
+        // Outra alice, em outro endereço, se registra no segundo tracker sem substituir a primeira
+        publish(&second_url, "alice", "127.0.0.1:9002", &manifest).await;
+        replicate(&first_state, first_state.federation.as_ref().unwrap()).await;
+        let chunks: Vec<ChunkRegister> = fetch(format!("{}/get_file_chunks?id={}&local=1", first_url, manifest.id)).await;
+        let mut addresses: Vec<&str> = chunks.iter().map(|chunk| chunk.peer_address.as_str()).collect();
+        addresses.sort();
+        assert_eq!(addresses, ["127.0.0.1:9001", "127.0.0.1:9001", "127.0.0.1:9002", "127.0.0.1:9002"]);
+        let peers: Vec<PeerInfo> = fetch(format!("{}/list", first_url)).await;
+        assert_eq!(peers.iter().filter(|peer| peer.name == "alice").count(), 2);
+    }
+}
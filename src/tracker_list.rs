@@ -0,0 +1,53 @@
+use rand::seq::SliceRandom;
+use std::sync::Mutex;
+
+/// **Lista de trackers em níveis (announce-list)**
+///
+/// Os trackers de um mesmo nível são equivalentes: a ordem dentro do nível é sorteada ao
+/// iniciar, e o tracker que responde passa para a frente do seu nível. As consultas percorrem
+/// os trackers nessa ordem, nível por nível, e passam para o próximo quando um está
+/// inacessível; os anúncios (registro, arquivos, heartbeats) vão para todos.
+pub struct TrackerList {
+    tiers: Mutex<Vec<Vec<String>>>,
+}
+
+impl TrackerList {
+    pub fn new(tiers: Vec<Vec<String>>) -> Self {
+        let mut rng = rand::thread_rng();
+        let tiers = tiers.into_iter()
+            .filter(|tier| !tier.is_empty())
+            .map(|mut tier| {
+                tier.shuffle(&mut rng);
+                tier
+            })
+            .collect();
+        TrackerList { tiers: Mutex::new(tiers) }
+    }
+
+    /// Todos os trackers, em ordem de prioridade
+    pub fn ordered(&self) -> Vec<String> {
+        self.tiers.lock().unwrap().iter().flatten().cloned().collect()
+    }
+
+    /// Tracker consultado primeiro no momento
+    pub fn primary(&self) -> String {
+        self.tiers.lock().unwrap().iter().flatten().next().cloned().unwrap_or_default()
+    }
+
+    /// Os níveis, como mostrados pela API
+    pub fn tiers(&self) -> Vec<Vec<String>> {
+        self.tiers.lock().unwrap().clone()
+    }
+
+    /// Passa `url` para a frente do seu nível depois de uma resposta
+    pub fn promote(&self, url: &str) {
+        let mut tiers = self.tiers.lock().unwrap();
+        for tier in tiers.iter_mut() {
+            if let Some(position) = tier.iter().position(|tracker| tracker == url) {
+                let tracker = tier.remove(position);
+                tier.insert(0, tracker);
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use serde::{Serialize, Deserialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::sync::Mutex;
+
+use crate::tracker::{ChunkRegister, FileRegister, Peer};
+
+/// **Operação registrada no journal do tracker**
+///
+/// Cada mutação do estado do tracker vira uma entrada; reaplicá-las em ordem
+/// reconstrói os mapas `peers`, `chunks` e `files`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum JournalEntry {
+    RegisterPeer { peer: Peer },
+    RegisterChunk { chunk: ChunkRegister },
+    RegisterFile { file: FileRegister },
+    UnregisterFile { peer: String, file: String }, // `file` é o ID do conteúdo
+    UnregisterChunk { peer: String, chunk: String },
+    UnregisterPeer { peer: String },
+}
+
+/// **Backend de persistência do tracker**
+pub trait TrackerStore: Send + Sync {
+    /// Lê todas as entradas persistidas, na ordem em que foram gravadas
+    fn load(&self) -> io::Result<Vec<JournalEntry>>;
+
+    /// Acrescenta uma entrada ao final do armazenamento
+    fn append(&self, entry: &JournalEntry) -> io::Result<()>;
+
+    /// Substitui todo o conteúdo por um snapshot equivalente ao estado atual
+    fn compact(&self, snapshot: &[JournalEntry]) -> io::Result<()>;
+}
+
+/// Backend que não persiste nada (comportamento original do tracker)
+pub struct MemoryStore;
+
+impl TrackerStore for MemoryStore {
+    fn load(&self) -> io::Result<Vec<JournalEntry>> {
+        Ok(vec![])
+    }
+
+    fn append(&self, _entry: &JournalEntry) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn compact(&self, _snapshot: &[JournalEntry]) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// **Journal append-only em JSON Lines**
+///
+/// Uma entrada por linha; linhas corrompidas (ex: escrita interrompida) são ignoradas na leitura.
+pub struct JournalStore {
+    path: String,
+    file: Mutex<File>,
+}
+
+impl JournalStore {
+    /// Abre (ou cria) o journal no caminho informado
+    pub fn open(path: &str) -> io::Result<JournalStore> {
+        let file = open_append(path)?;
+        Ok(JournalStore {
+            path: path.to_string(),
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl TrackerStore for JournalStore {
+    fn load(&self) -> io::Result<Vec<JournalEntry>> {
+        let reader = BufReader::new(File::open(&self.path)?);
+        let mut entries = vec![];
+
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => println!("⚠️ Linha {} do journal '{}' ignorada: {}", number + 1, self.path, e),
+            }
+        }
+
+        Ok(entries)
+    }
+
+    fn append(&self, entry: &JournalEntry) -> io::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())?;
+        file.flush()
+    }
+
+    fn compact(&self, snapshot: &[JournalEntry]) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        let tmp_path = format!("{}.tmp", self.path);
+
+        {
+            let mut tmp = File::create(&tmp_path)?;
+            for entry in snapshot {
+                let mut line = serde_json::to_string(entry)?;
+                line.push('\n');
+                tmp.write_all(line.as_bytes())?;
+            }
+            tmp.sync_all()?;
+        }
+
+        // Troca atômica do journal antigo pelo snapshot e reabre para novos appends
+        fs::rename(&tmp_path, &self.path)?;
+        *file = open_append(&self.path)?;
+        Ok(())
+    }
+}
+
+fn open_append(path: &str) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}